@@ -1,10 +1,10 @@
-/* Doesn't work. Need to implement infix operators 
+/* Infix operators. + - * / ^ < are built in, others can be declared with :- op(Priority, Type, Name). */
 A + B <- B + A.
 A + (B + C) <-> (A + B) + C.
 
 A * B <- B * A.
 A * (B * C) <-> (A * B) * C.
-*/
+
 plus(X,Y) <- plus(Y,X).
 plus(plus(X,Y),Z) <-> plus(X,plus(Y,Z)).
 mul(X,Y) <- mul(Y,X).
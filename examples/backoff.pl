@@ -0,0 +1,9 @@
+% The backoff scheduler bans rules that match too often. Limits can be set
+% for all rules with --match-limit and --ban-length, or per rule.
+comm :: plus(X, Y) <-> plus(Y, X).
+assoc :: plus(X, plus(Y, Z)) <-> plus(plus(X, Y), Z).
+:- match_limit(assoc, 4).
+:- ban_length(assoc, 2).
+
+plus(a, plus(b, plus(c, d))).
+?- plus(a, plus(b, plus(c, d))) = plus(d, plus(c, plus(b, a))).
@@ -24,7 +24,7 @@ fiz(baz) <- bar(boo), x = z.
 
 
 /* Queries
-Note that this does NOT insert into the egraph. Should I change that? Or give a new notation for "insert all subterms and then query"?
+Note that this does NOT insert into the egraph. ?+ inserts the ground subterms first, see insert.pl.
  */
 ?- f(x) = x.
 ?- x = x.
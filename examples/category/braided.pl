@@ -1,4 +1,4 @@
-:- include(examples/category/monoidal.pl).
+:- include(monoidal.pl).
 /* right over left = rol */
 /* left over right = lor */
 /*
@@ -1,5 +1,5 @@
 
-:- include(examples/category/braided.pl).
+:- include(braided.pl).
 
 type(a) = ob.
 type(b) = ob.
@@ -1,4 +1,4 @@
-:- include(examples/category/symmetric.pl).
+:- include(symmetric.pl).
 
 comp(dup(A), otimes(del(A), id(A)) <-> id(A).
 comp(dup(A), otimes(id(A), del(A)) <-> id(A).
@@ -1,4 +1,4 @@
- :- include(examples/category/category.pl).
+ :- include(category.pl).
 /*  We should make this better */
 
 /* Monoidal. This just sends it off into the stratosphere.
@@ -1,4 +1,4 @@
-:- include(examples/category/monoidal.pl).
+:- include(monoidal.pl).
 
 type(a) = ob.
 type(b) = ob.
@@ -1,4 +1,4 @@
-:- include(examples/category/monoidal.pl).
+:- include(monoidal.pl).
 
 id(A) <- swap(A, munit). 
 id(A) <- swap(munit,A).
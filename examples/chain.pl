@@ -0,0 +1,19 @@
+/* Chains of equations. A fact or rule head unions all the terms,
+   a body or query needs them all in one eclass. */
+a = b = c.
+d = e.
+p(a).
+
+f(X) = g(X) = h(X) :- p(X).
+q(X) :- f(X) = g(X) = h(X).
+r(X) <- s(X), X = b = c.
+s(a).
+
+?- a = b = c.
+?- f(a) = g(c) = h(b).
+?- q(b).
+?- r(c) = s(a).
+?- a = b = d.
+
+Axiom ax: forall x, p x => k x = l x = m x.
+|- k c = l b = m a.
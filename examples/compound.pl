@@ -0,0 +1,13 @@
+% A goal made of several parts asks a query for each of them,
+% and then says whether the whole goal was proved.
+Axiom comm: forall x y s, plus x y = s => plus y x = s.
+Axiom dbl: forall x, double x => plus x x = twice x.
+plus(one, two) = three.
+double(one).
+edge(a, b).
+edge(b, c).
+
+|- plus two one = three /\ (forall x, x = one => plus x x = twice one).
+|- plus one one = three \/ plus one one = twice one.
+|- (exists y, edge a y /\ edge y c) /\ edge c a.
+|- forall z, z = one => (exists b, plus z b = three) \/ edge z a.
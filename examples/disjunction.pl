@@ -0,0 +1,20 @@
+% ; is or, and , binds tighter, so a, b ; c is (a, b) ; c.
+start(a).
+edge(a, b).
+edge(b, c).
+edge(x, y).
+reach(X) :- start(X) ; (edge(Y, X), reach(Y)).
+
+% An answer only binds the variables every branch has
+?- reach(X).
+?- edge(X, b) ; start(X).
+?- edge(X, Y), (Y = c ; X = x).
+
+% Guards of rewrites, and \/ in the hypothesis of an axiom
+color(X) <- car(X), (red(X) ; blue(X)).
+car(c1).
+car(c2).
+red(c1).
+Axiom paint: forall x, red x \/ blue x => painted x.
+?- color(X) = car(X).
+?- painted(X).
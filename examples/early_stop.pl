@@ -0,0 +1,12 @@
+% A run stops as soon as every query after it is proven, unless one of them has
+% variables to answer. --all-answers keeps going to the limits.
+:- iter_limit(5).
+nat(z).
+nat(s(X)) :- nat(X).
+
+% Stops once nat(s(s(z))) is found
+:- run.
+?- nat(s(s(z))).
+% Runs the one iteration, since nat(X) wants every answer
+:- run(1).
+?- nat(X).
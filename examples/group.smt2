@@ -0,0 +1,18 @@
+; Cancellation from the group axioms.
+(set-logic UF)
+(declare-sort G 0)
+(declare-fun e () G)
+(declare-fun mul (G G) G)
+(declare-fun inv (G) G)
+(declare-fun a () G)
+(declare-fun b () G)
+
+(assert (forall ((x G) (y G) (z G))
+  (! (= (mul (mul x y) z) (mul x (mul y z))) :pattern ((mul (mul x y) z)))))
+(assert (forall ((x G) (y G) (z G))
+  (! (= (mul x (mul y z)) (mul (mul x y) z)) :pattern ((mul x (mul y z))))))
+(assert (forall ((x G)) (= (mul e x) x)))
+(assert (forall ((x G)) (! (= (mul (inv x) x) e) :pattern ((inv x)))))
+
+(assert (not (= (mul (inv a) (mul a b)) b)))
+(check-sat)
@@ -0,0 +1,18 @@
+% Axioms are Harrop program clauses: facts, equations, and rules G => D,
+% where the hypothesis G is searched for and the conclusion D can be another clause.
+Axiom fg: forall x, f x = g x.
+Axiom curry: forall x, parent x => (forall y, child y x => (ancestor x y /\ (older x y => wiser x y))).
+Axiom found: forall x, (exists y, child x y) => has_parent x.
+
+parent(ann).
+child(bob, ann).
+older(ann, bob).
+f(a) = c.
+
+?- g(a) = c.
+?- ancestor(X, Y).
+?- wiser(ann, bob).
+?- has_parent(X).
+
+% A goal can assume axioms of its own
+|- forall u, (forall x, child x u => parent u) => child bob u => parent u.
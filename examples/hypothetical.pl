@@ -0,0 +1,15 @@
+% A => B in a goal is proven in a copy of the egraph with A added.
+% The copy is thrown away afterwards, so nothing assumed leaks into the rest of the file.
+Axiom comm: forall x y s, plus x y = s => plus y x = s.
+plus(one, two) = three.
+|- forall a b, a = b => f a = f b.
+|- forall a, a = c => (h c = d => h a = d).
+|- forall a b, (forall x, p x => q x) => p a => q a.
+|- a = b => plus two one = three /\ g a = g b.
+
+% Nothing assumed above holds here
+p(e).
+?- a = b.
+?- q(e).
+?- plus(two, one) = three.
+?- g(a) = g(b).
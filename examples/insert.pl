@@ -0,0 +1,9 @@
+% ?- only looks at what is already in the egraph.
+% ?+ first adds the ground subterms of the query, runs the rules and then answers.
+X <- f(f(f(X))).
+X <- f(f(f(f(f(X))))).
+
+?- f(f(f(y))) = y.
+?+ f(f(f(x))) = x.
+?+ f(f(f(f(f(a))))) = a, f(a) = a.
+?+ f(b) = b.
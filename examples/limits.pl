@@ -0,0 +1,8 @@
+% Limits can be set in the file. --iter-limit etc. on the command line win.
+:- iter_limit(3).
+
+n(z).
+n(s(X)) :- n(X).
+
+?- n(s(s(z))).
+?- n(s(s(s(s(s(z)))))).
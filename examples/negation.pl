@@ -0,0 +1,25 @@
+% \+ p(X) (or not p(X)) holds when p(X) can't be found.
+% The rules are run in strata, so reach is saturated before unreachable looks at it.
+edge(a, b).
+edge(b, c).
+node(a).
+node(b).
+node(c).
+node(d).
+reach(X, Y) :- edge(X, Y).
+reach(X, Z) :- edge(X, Y), reach(Y, Z).
+unreachable(X) :- node(X), \+ reach(a, X).
+
+% A variable only under the negation means there is no such thing at all
+sink(X) :- node(X), not edge(X, _).
+
+% Rewrites can be guarded by negations too. They run last, once heavy has saturated.
+big(anvil).
+heavy(X) :- big(X).
+item(anvil).
+item(feather).
+light(X) <- item(X), \+ heavy(X).
+
+?- unreachable(X).
+?- sink(X).
+?- item(X) = light(X).
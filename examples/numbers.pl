@@ -0,0 +1,15 @@
+/* Integer, rational and "string" literals. The built in + - * / < = (also plus, sub, mul, div)
+   are evaluated whenever they get applied to literals in the egraph. */
+fib(0) = 0.
+fib(1) = 1.
+fib(N - 1) + fib(N - 2) <- fib(N), 1 < N = true.
+fib(20).
+?- fib(20) = X.
+
+half = 1/2.
+third = 1/3.
+half + third.
+?- half + third = X.
+
+name(ann) = "Ann".
+?- name(X) = "Ann".
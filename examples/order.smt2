@@ -0,0 +1,10 @@
+; Horn clauses. A predicate (le x y) stands for (= (le x y) true)
+(set-logic UFLIA)
+(declare-fun le (Int Int) Bool)
+(assert (le 1 2))
+(assert (le 2 3))
+(assert (forall ((x Int) (y Int) (z Int)) (=> (and (le x y) (le y z)) (le x z))))
+(assert (not (le 3 1)))
+(check-sat)
+(assert (not (le 1 3)))
+(check-sat)
@@ -0,0 +1,23 @@
+% Queries are answered against the egraph as it is at that point in the file.
+% Once there is a :- run. the rules only run where asked.
+edge(a, b).
+edge(b, c).
+edge(c, d).
+path(X, Y) :- edge(X, Y).
+path(X, Z) :- edge(X, Y), path(Y, Z).
+
+?- path(a, b).
+:- run(1).
+?- path(a, b).
+?- path(a, c).
+:- run(1).
+?- path(a, c).
+?- path(a, d).
+:- saturate.
+?- path(a, d).
+
+% Facts and rules added after a run are only seen by later runs.
+edge(d, e).
+?- path(a, e).
+:- run.
+?- path(a, e).
@@ -0,0 +1,27 @@
+% Rules can be named with name :: rule, and grouped with :- ruleset(name).
+% A schedule runs rulesets in phases on the same egraph.
+:- ruleset(datalog).
+base :: path(X, Y) :- edge(X, Y).
+step :: path(X, Z) :- edge(X, Y), path(Y, Z).
+
+:- ruleset(rewrites).
+comm :: plus(X, Y) <-> plus(Y, X).
+assoc :: plus(X, plus(Y, Z)) <-> plus(plus(X, Y), Z).
+
+edge(a, b).
+edge(b, c).
+edge(c, d).
+plus(a, plus(b, c)).
+
+:- schedule(repeat(1, datalog)).
+?- path(a, c).
+:- schedule(seq(saturate(datalog), repeat(2, rewrites))).
+?- path(a, d).
+?- plus(a, plus(b, c)) = plus(c, plus(b, a)).
+
+% Switch off a rule by name
+:- disable(step).
+edge(d, e).
+:- schedule(saturate(datalog)).
+?- path(d, e).
+?- path(a, e).
@@ -0,0 +1,31 @@
+% :- push. saves the egraph and rules, :- pop. goes back to them.
+% The base theory is only saturated once.
+Y <- plus(z, Y).
+s(plus(X, Y)) <- plus(s(X), Y).
+two = s(s(z)).
+four = plus(two, two).
+:- saturate.
+?- four = s(s(s(s(z)))).
+
+:- push.
+two = three.
+:- run.
+?- four = plus(three, three).
+:- pop.
+?- four = plus(three, three).
+
+:- push.
+plus(X, X) <- double(X).
+double(two).
+:- run.
+?- double(two) = four.
+:- pop.
+double(two).
+:- run.
+?- double(two) = four.
+
+% :- clear. forgets everything
+:- clear.
+two.
+:- run.
+?- two = s(s(z)).
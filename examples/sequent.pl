@@ -0,0 +1,7 @@
+% A goal is taken apart with the rules of the sequent calculus, and each leaf is asked
+% of the egraph. The proof tree shows which query closed which leaf.
+Axiom trans: forall x y z, le x y /\ le y z => le x z.
+le(a, b).
+
+|- forall u v, u = v /\ v = w => f u = f w /\ (le a v => le a w).
+|- forall u, le b u => le a u /\ (le u c \/ le a u).
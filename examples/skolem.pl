@@ -0,0 +1,12 @@
+% exists in the conclusion of an axiom names a new term, built from the variables
+% of the hypothesis. The new symbols start with #sk_ so they can't clash with yours.
+Axiom succ: forall x, nat x => (exists y, nat y /\ lt x y = true).
+Axiom inv: forall x y, mul x y = one => (exists z, mul y z = one).
+Axiom origin: exists o, point o /\ dist o o = zero.
+
+nat(z).
+mul(a, b) = one.
+:- run(2).
+?- lt(z, Y) = true, lt(Y, W) = true.
+?- mul(b, Z) = one.
+?- point(O).
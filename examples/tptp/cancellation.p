@@ -0,0 +1,5 @@
+% Left cancellation, as a negated conjecture.
+include('Axioms/GRP004-0.ax').
+
+cnf(prove_cancellation,negated_conjecture,
+    multiply(inverse(a),multiply(a,b)) != b ).
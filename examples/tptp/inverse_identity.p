@@ -0,0 +1,5 @@
+% The inverse of the identity, and a conjecture that doesn't follow.
+include('Axioms/GRP004-0.ax',[left_identity,left_inverse]).
+
+fof(inverse_identity,conjecture,
+    inverse(identity) = identity ).
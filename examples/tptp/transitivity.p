@@ -0,0 +1,9 @@
+% Horn clauses in fof, with a universally quantified conjecture.
+fof(le_trans,axiom,
+    ! [X,Y,Z] : ( ( le(X,Y) & le(Y,Z) ) => le(X,Z) ) ).
+fof(le_succ,axiom,
+    ! [X] : ( nat(X) => ( le(X,s(X)) & nat(s(X)) ) ) ).
+fof(zero,axiom, nat(zero) ).
+
+fof(two_steps,conjecture,
+    ? [Y] : ( le(zero,s(Y)) & le(s(zero),Y) ) ).
@@ -0,0 +1,18 @@
+% exists! is a witness, and a rule that any two witnesses are equal.
+Axiom unit: exists! e, identity e.
+Axiom inv: forall x, elem x => (exists! y, inverse x y).
+
+identity(one).
+identity(id2).
+elem(a).
+elem(b).
+inverse(a, b).
+inverse(a, c).
+
+?- one = id2.
+?- b = c.
+
+% As a goal, every answer has to be in the same eclass
+|- exists! e, identity e.
+|- exists! y, inverse a y.
+|- exists! x, elem x.
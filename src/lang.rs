@@ -0,0 +1,94 @@
+use crate::types::Literal;
+use egg::*;
+use num_traits::Zero;
+use std::sync::Arc;
+
+define_language! {
+    pub enum EggLog {
+        Lit(Literal),
+        Fun(Symbol, Vec<Id>),
+    }
+}
+
+impl EggLog {
+    pub fn constant(name: &str) -> Self {
+        EggLog::Fun(Symbol::from(name), vec![])
+    }
+}
+
+// Constant folding of the built in operators. The data of an eclass is the literal
+// (or true/false) it is known to equal, which modify adds into the class.
+#[derive(Default, Debug, Clone)]
+pub struct ConstFold;
+
+fn fold(op: &str, args: &[&Literal]) -> Option<EggLog> {
+    use Literal::*;
+    let num = |i: usize| args[i].as_number();
+    let truth = |b: bool| EggLog::constant(if b { "true" } else { "false" });
+    let lit = match (op, args.len()) {
+        ("+", 2) | ("plus", 2) => Literal::number(num(0)? + num(1)?),
+        ("-", 2) | ("sub", 2) => Literal::number(num(0)? - num(1)?),
+        ("*", 2) | ("mul", 2) => Literal::number(num(0)? * num(1)?),
+        ("/", 2) | ("div", 2) => {
+            let d = num(1)?;
+            if d.is_zero() {
+                return None;
+            }
+            Literal::number(num(0)? / d)
+        }
+        ("-", 1) => Literal::number(-num(0)?),
+        ("<", 2) => match (args[0], args[1]) {
+            (Str(a), Str(b)) => return Some(truth(a < b)),
+            _ => return Some(truth(num(0)? < num(1)?)),
+        },
+        // Literals are kept canonical so structural equality is the right thing
+        ("=", 2) => return Some(truth(args[0] == args[1])),
+        _ => return None,
+    };
+    Some(EggLog::Lit(lit))
+}
+
+impl Analysis<EggLog> for ConstFold {
+    type Data = Option<EggLog>;
+
+    fn make(egraph: &EGraph<EggLog, Self>, enode: &EggLog) -> Self::Data {
+        match enode {
+            EggLog::Lit(_) => Some(enode.clone()),
+            EggLog::Fun(f, args) if !args.is_empty() => {
+                let lits: Option<Vec<&Literal>> = args
+                    .iter()
+                    .map(|a| match &egraph[*a].data {
+                        Some(EggLog::Lit(l)) => Some(l),
+                        _ => None,
+                    })
+                    .collect();
+                fold(f.as_str(), &lits?)
+            }
+            EggLog::Fun(..) => None,
+        }
+    }
+
+    // A class with two different constants in it means the program asserted something like 1 = 2.
+    // That is the user's business, we just keep the first one.
+    fn merge(&self, to: &mut Self::Data, from: Self::Data) -> bool {
+        if to.is_none() && from.is_some() {
+            *to = from;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn modify(egraph: &mut EGraph<EggLog, Self>, id: Id) {
+        if let Some(c) = egraph[id].data.clone() {
+            let mut lit = PatternAst::default();
+            lit.add(ENodeOrVar::ENode(c));
+            let v: Var = "?x".parse().unwrap();
+            let mut class = PatternAst::default();
+            class.add(ENodeOrVar::Var(v));
+            let mut subst = Subst::with_capacity(1);
+            subst.insert(v, id);
+            egraph.union_instantiations(&lit, &class, &subst, Arc::from("Constant Folding"));
+        }
+    }
+}
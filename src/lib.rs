@@ -2,8 +2,10 @@ use egg::*;
 use std::fmt::Write;
 use std::sync::Arc;
 mod gensym;
+mod lang;
 mod logic;
 mod types;
+pub use lang::*;
 pub use types::*;
 use Entry::*;
 use EqWrap::*;
@@ -11,6 +13,12 @@ use Term::*;
 //use types::Directive::*;
 mod parser;
 pub use parser::*;
+mod smt2;
+pub use smt2::*;
+mod tptp;
+pub use tptp::*;
+mod scheduler;
+pub use scheduler::*;
 
 fn merge_subst2(s1: &Subst, s2: &Subst) -> Option<Subst> {
     let mut s1 = s1.clone();
@@ -92,6 +100,23 @@ impl<L: Language, A: Analysis<L>> Searcher<L, A> for EqWrap<Pattern<L>> {
                     })
                 }
             }
+            // Every term in this one eclass
+            MultiEq(ps) => {
+                let mut substs = vec![Subst::with_capacity(0)];
+                for p in ps {
+                    let matches = p.search_eclass(egraph, eclass)?;
+                    substs = merge_substs(&substs, &matches.substs);
+                }
+                if substs.len() == 0 {
+                    None
+                } else {
+                    Some(SearchMatches {
+                        eclass,
+                        substs,
+                        ast: None,
+                    })
+                }
+            }
         }
     }
     fn vars(&self) -> Vec<egg::Var> {
@@ -102,6 +127,7 @@ impl<L: Language, A: Analysis<L>> Searcher<L, A> for EqWrap<Pattern<L>> {
                 vars.extend(r.vars());
                 vars
             }
+            MultiEq(ps) => ps.iter().flat_map(|p| p.vars()).collect(),
         }
     }
 }
@@ -137,6 +163,500 @@ impl<L: Language, A: Analysis<L>, P: Searcher<L, A>> Searcher<L, A> for MultiPat
     }
 }
 
+// Only succeeds if P fails. The matches of `pat` that no match of a negated condition
+// agrees with, so `\+ q(X, Y)` with Y unbound means there is no q(X, _) at all.
+#[derive(Debug, PartialEq, Clone)]
+struct NegPattern<S, P> {
+    pat: S,
+    negs: Vec<P>,
+}
+
+impl<S: fmt::Display, P: fmt::Display> fmt::Display for NegPattern<S, P> {
+    fn fmt(&self, buf: &mut fmt::Formatter) -> fmt::Result {
+        write!(buf, "{}", self.pat)?;
+        for neg in &self.negs {
+            write!(buf, ", \\+ {}", neg)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S, P> NegPattern<S, P> {
+    // Every match of the negated conditions
+    fn found<L, A>(&self, egraph: &EGraph<L, A>) -> Vec<Subst>
+    where
+        L: Language,
+        A: Analysis<L>,
+        P: Searcher<L, A>,
+    {
+        self.negs
+            .iter()
+            .flat_map(|neg| neg.search(egraph))
+            .flat_map(|m| m.substs)
+            .collect()
+    }
+
+    fn keep<L: Language>(
+        &self,
+        mut matches: SearchMatches<L>,
+        found: &[Subst],
+    ) -> Option<SearchMatches<L>> {
+        matches
+            .substs
+            .retain(|s| found.iter().all(|f| merge_subst2(s, f).is_none()));
+        if matches.substs.is_empty() {
+            None
+        } else {
+            Some(matches)
+        }
+    }
+}
+
+impl<L, A, S, P> Searcher<L, A> for NegPattern<S, P>
+where
+    L: Language,
+    A: Analysis<L>,
+    S: Searcher<L, A>,
+    P: Searcher<L, A>,
+{
+    fn search_eclass(&self, egraph: &EGraph<L, A>, eclass: Id) -> Option<SearchMatches<L>> {
+        let matches = self.pat.search_eclass(egraph, eclass)?;
+        let found = self.found(egraph);
+        self.keep(matches, &found)
+    }
+    // The negated conditions are only searched once. Alternatives can find the same
+    // answer in different eclasses, so each one is only kept the first time.
+    fn search(&self, egraph: &EGraph<L, A>) -> Vec<SearchMatches<L>> {
+        let found = self.found(egraph);
+        let mut seen = HashSet::new();
+        self.pat
+            .search(egraph)
+            .into_iter()
+            .filter_map(|mut m| {
+                m.substs.retain(|s| seen.insert(s.clone()));
+                self.keep(m, &found)
+            })
+            .collect()
+    }
+    // A negation never binds anything
+    fn vars(&self) -> Vec<egg::Var> {
+        self.pat.vars()
+    }
+}
+
+// Alt pattern implements "Or" search.
+// It should run each of it's searchers and collate the results.
+// Unlike MultiPattern it make no sense as an Applier, since we don't know which case to use.
+// Only the variables every branch binds are kept.
+#[derive(Debug, PartialEq, Clone)]
+struct AltPattern<P> {
+    pats: Vec<P>,
+}
+
+impl<P: fmt::Display> fmt::Display for AltPattern<P> {
+    fn fmt(&self, buf: &mut fmt::Formatter) -> fmt::Result {
+        let pats: Vec<String> = self.pats.iter().map(|p| p.to_string()).collect();
+        write!(buf, "({})", pats.join(" ; "))
+    }
+}
+
+impl<P> AltPattern<P> {
+    // The matches of each branch, rooted at `root` if there is one
+    fn found<L, A>(&self, egraph: &EGraph<L, A>, root: Option<Id>) -> Vec<Vec<Subst>>
+    where
+        L: Language,
+        A: Analysis<L>,
+        P: Searcher<L, A>,
+    {
+        self.pats
+            .iter()
+            .map(|pat| {
+                let matches = match root {
+                    Some(eclass) => pat.search_eclass(egraph, eclass).into_iter().collect(),
+                    None => pat.search(egraph),
+                };
+                matches.into_iter().flat_map(|m| m.substs).collect()
+            })
+            .collect()
+    }
+
+    // The matches of each branch, by eclass in the egraph's order
+    fn found_by_class<L, A>(&self, egraph: &EGraph<L, A>) -> Vec<(Id, Vec<Vec<Subst>>)>
+    where
+        L: Language,
+        A: Analysis<L>,
+        P: Searcher<L, A>,
+    {
+        let mut by_class: HashMap<Id, Vec<Vec<Subst>>> = HashMap::new();
+        for (i, pat) in self.pats.iter().enumerate() {
+            for m in pat.search(egraph) {
+                let found = by_class
+                    .entry(m.eclass)
+                    .or_insert_with(|| vec![vec![]; self.pats.len()]);
+                found[i].extend(m.substs);
+            }
+        }
+        egraph
+            .classes()
+            .filter_map(|c| Some((c.id, by_class.remove(&c.id)?)))
+            .collect()
+    }
+
+    // Each branch's matches joined with the substitutions so far. What a branch binds
+    // that the others don't, the `common` variables, is dropped again.
+    fn join(common: &[egg::Var], found: &[Vec<Subst>], substs: &[Subst]) -> Vec<Subst> {
+        let mut out: Vec<Subst> = vec![];
+        for found in found {
+            for s in substs {
+                for t in found {
+                    if let Some(merged) = merge_subst2(s, t) {
+                        let mut kept = Subst::with_capacity(merged.vec.len());
+                        for (v, id) in merged.vec.iter() {
+                            if s.get(*v).is_some() || common.contains(v) {
+                                kept.insert(*v, *id);
+                            }
+                        }
+                        if !out.contains(&kept) {
+                            out.push(kept);
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+impl<L: Language, A: Analysis<L>, P: Searcher<L, A>> Searcher<L, A> for AltPattern<P> {
+    fn search_eclass(&self, egraph: &EGraph<L, A>, eclass: Id) -> Option<SearchMatches<L>> {
+        let found = self.found(egraph, Some(eclass));
+        let substs = Self::join(&self.vars(), &found, &[Subst::with_capacity(0)]);
+        if substs.is_empty() {
+            None
+        } else {
+            Some(SearchMatches {
+                eclass,
+                substs,
+                ast: None,
+            })
+        }
+    }
+    // Each branch is only searched once
+    fn search(&self, egraph: &EGraph<L, A>) -> Vec<SearchMatches<L>> {
+        let common = self.vars();
+        self.found_by_class(egraph)
+            .into_iter()
+            .filter_map(|(eclass, found)| {
+                let substs = Self::join(&common, &found, &[Subst::with_capacity(0)]);
+                if substs.is_empty() {
+                    None
+                } else {
+                    Some(SearchMatches {
+                        eclass,
+                        substs,
+                        ast: None,
+                    })
+                }
+            })
+            .collect()
+    }
+    fn vars(&self) -> Vec<egg::Var> {
+        let mut vars = self.pats.iter().map(|p| p.vars());
+        let first = vars.next().unwrap_or_default();
+        vars.fold(first, |common, vs| {
+            common.into_iter().filter(|v| vs.contains(v)).collect()
+        })
+    }
+}
+
+// One condition of a body or query, ready to search. Negations are left to NegPattern.
+#[derive(Debug, PartialEq, Clone)]
+enum CondPattern {
+    Pos(EqWrap<Pattern<EggLog>>),
+    Alt(AltPattern<LogBody>),
+}
+
+impl fmt::Display for CondPattern {
+    fn fmt(&self, buf: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CondPattern::Pos(p) => write!(buf, "{}", p),
+            CondPattern::Alt(p) => write!(buf, "{}", p),
+        }
+    }
+}
+
+// The conditions joined in order, like MultiPattern. An alternative is joined with what
+// came before it, so `p(X, Y), (Y = c ; X = x)` checks the X and Y from p.
+#[derive(Debug, PartialEq, Clone)]
+struct BodyPattern {
+    conds: Vec<CondPattern>,
+}
+
+impl fmt::Display for BodyPattern {
+    fn fmt(&self, buf: &mut fmt::Formatter) -> fmt::Result {
+        let conds: Vec<String> = self.conds.iter().map(|c| c.to_string()).collect();
+        write!(buf, "{}", conds.join(", "))
+    }
+}
+
+impl CondPattern {
+    // The matches of an equation, or of each branch of an alternative, rooted at `root`
+    // if there is one
+    fn found<A: Analysis<EggLog>>(
+        &self,
+        egraph: &EGraph<EggLog, A>,
+        root: Option<Id>,
+    ) -> Vec<Vec<Subst>> {
+        match self {
+            CondPattern::Pos(p) => {
+                let matches = match root {
+                    Some(eclass) => p.search_eclass(egraph, eclass).into_iter().collect(),
+                    None => p.search(egraph),
+                };
+                matches.into_iter().map(|m| m.substs).collect()
+            }
+            CondPattern::Alt(alt) => alt.found(egraph, root),
+        }
+    }
+
+    // The same, by eclass in the egraph's order
+    fn found_by_class<A: Analysis<EggLog>>(
+        &self,
+        egraph: &EGraph<EggLog, A>,
+    ) -> Vec<(Id, Vec<Vec<Subst>>)> {
+        match self {
+            CondPattern::Pos(p) => p
+                .search(egraph)
+                .into_iter()
+                .map(|m| (m.eclass, vec![m.substs]))
+                .collect(),
+            CondPattern::Alt(alt) => alt.found_by_class(egraph),
+        }
+    }
+
+    fn vars<A: Analysis<EggLog>>(&self) -> Vec<egg::Var> {
+        match self {
+            CondPattern::Pos(p) => <EqWrap<_> as Searcher<EggLog, A>>::vars(p),
+            CondPattern::Alt(p) => <AltPattern<_> as Searcher<EggLog, A>>::vars(p),
+        }
+    }
+
+    // What it found joined with the substitutions so far
+    fn join<A: Analysis<EggLog>>(&self, found: &[Vec<Subst>], substs: &[Subst]) -> Vec<Subst> {
+        match self {
+            CondPattern::Pos(_) => found
+                .iter()
+                .flat_map(|f| {
+                    substs
+                        .iter()
+                        .flat_map(move |s| f.iter().filter_map(move |t| merge_subst2(s, t)))
+                })
+                .collect(),
+            CondPattern::Alt(_) => AltPattern::<LogBody>::join(&self.vars::<A>(), found, substs),
+        }
+    }
+}
+
+impl BodyPattern {
+    // The first condition's matches in `eclass` joined with the rest in order
+    fn joined<A: Analysis<EggLog>>(
+        &self,
+        eclass: Id,
+        first: &[Vec<Subst>],
+        rest: &[Vec<Vec<Subst>>],
+    ) -> Option<SearchMatches<EggLog>> {
+        let mut substs = vec![Subst::with_capacity(0)];
+        let found = std::iter::once(first).chain(rest.iter().map(|f| &f[..]));
+        for (cond, found) in self.conds.iter().zip(found) {
+            substs = cond.join::<A>(found, &substs);
+            if substs.is_empty() {
+                return None;
+            }
+        }
+        Some(SearchMatches {
+            eclass,
+            substs,
+            ast: None,
+        })
+    }
+
+    fn rest<A: Analysis<EggLog>>(&self, egraph: &EGraph<EggLog, A>) -> Vec<Vec<Vec<Subst>>> {
+        self.conds[1..]
+            .iter()
+            .map(|c| c.found(egraph, None))
+            .collect()
+    }
+}
+
+impl<A: Analysis<EggLog>> Searcher<EggLog, A> for BodyPattern {
+    // The first condition is the one rooted at eclass
+    fn search_eclass(
+        &self,
+        egraph: &EGraph<EggLog, A>,
+        eclass: Id,
+    ) -> Option<SearchMatches<EggLog>> {
+        let first = self.conds[0].found(egraph, Some(eclass));
+        if first.iter().all(|f| f.is_empty()) {
+            return None;
+        }
+        self.joined::<A>(eclass, &first, &self.rest(egraph))
+    }
+    // The conditions after the first, alternatives and their negations included, are
+    // only searched once rather than once per eclass
+    fn search(&self, egraph: &EGraph<EggLog, A>) -> Vec<SearchMatches<EggLog>> {
+        let rest = self.rest(egraph);
+        self.conds[0]
+            .found_by_class(egraph)
+            .into_iter()
+            .filter_map(|(eclass, first)| self.joined::<A>(eclass, &first, &rest))
+            .collect()
+    }
+    fn vars(&self) -> Vec<egg::Var> {
+        let mut vars: Vec<_> = self.conds.iter().flat_map(|c| c.vars::<A>()).collect();
+        vars.sort();
+        vars.dedup();
+        vars
+    }
+}
+
+// A body or query. The negated conditions filter the matches of the rest.
+type LogBody = NegPattern<BodyPattern, EqWrap<Pattern<EggLog>>>;
+
+impl LogBody {
+    fn of_patterns(patterns: Vec<EqWrap<Pattern<EggLog>>>) -> Self {
+        NegPattern {
+            pat: BodyPattern {
+                conds: patterns.into_iter().map(CondPattern::Pos).collect(),
+            },
+            negs: vec![],
+        }
+    }
+
+    // Whether a \+ anywhere in it, alternatives included, could stop matching later
+    fn negates(&self) -> bool {
+        !self.negs.is_empty()
+            || self.pat.conds.iter().any(|c| match c {
+                CondPattern::Pos(_) => false,
+                CondPattern::Alt(alt) => alt.pats.iter().any(|b| b.negates()),
+            })
+    }
+
+    // The equations at the top, which are what proofs are printed for
+    fn equations(&self) -> impl Iterator<Item = &EqWrap<Pattern<EggLog>>> {
+        self.pat.conds.iter().filter_map(|c| match c {
+            CondPattern::Pos(p) => Some(p),
+            CondPattern::Alt(_) => None,
+        })
+    }
+}
+
+fn compile_body(body: &[Cond]) -> Result<LogBody, String> {
+    let mut conds = vec![];
+    let mut negs = vec![];
+    for cond in body {
+        match cond {
+            Cond::Pos(e) => conds.push(CondPattern::Pos(pattern_of_eqterm(e))),
+            Cond::Not(e) => negs.push(pattern_of_eqterm(e)),
+            Cond::Or(branches) => {
+                let pats = branches
+                    .iter()
+                    .map(|b| compile_body(b))
+                    .collect::<Result<_, _>>()?;
+                conds.push(CondPattern::Alt(AltPattern { pats }));
+            }
+        }
+    }
+    if conds.is_empty() {
+        let body: Vec<String> = body.iter().map(|c| c.to_string()).collect();
+        return Err(format!("{} needs a condition without \\+", body.join(", ")));
+    }
+    Ok(NegPattern {
+        pat: BodyPattern { conds },
+        negs,
+    })
+}
+
+// The matches of `pat` that some match of each guard agrees with. Nothing the guards bind
+// is kept, so the `(c ; d)` in `a <- b, (c ; d)` is only checked.
+struct GuardPattern<S, P> {
+    pat: S,
+    guards: Vec<P>,
+}
+
+impl<S: fmt::Display, P: fmt::Display> fmt::Display for GuardPattern<S, P> {
+    fn fmt(&self, buf: &mut fmt::Formatter) -> fmt::Result {
+        write!(buf, "{}", self.pat)?;
+        for guard in &self.guards {
+            write!(buf, ", {}", guard)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S, P> GuardPattern<S, P> {
+    // Every match of each guard
+    fn found<L, A>(&self, egraph: &EGraph<L, A>) -> Vec<Vec<Subst>>
+    where
+        L: Language,
+        A: Analysis<L>,
+        P: Searcher<L, A>,
+    {
+        self.guards
+            .iter()
+            .map(|guard| {
+                guard
+                    .search(egraph)
+                    .into_iter()
+                    .flat_map(|m| m.substs)
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn keep<L: Language>(
+        &self,
+        mut matches: SearchMatches<L>,
+        found: &[Vec<Subst>],
+    ) -> Option<SearchMatches<L>> {
+        for found in found {
+            matches
+                .substs
+                .retain(|s| found.iter().any(|f| merge_subst2(s, f).is_some()));
+        }
+        if matches.substs.is_empty() {
+            None
+        } else {
+            Some(matches)
+        }
+    }
+}
+
+impl<L, A, S, P> Searcher<L, A> for GuardPattern<S, P>
+where
+    L: Language,
+    A: Analysis<L>,
+    S: Searcher<L, A>,
+    P: Searcher<L, A>,
+{
+    fn search_eclass(&self, egraph: &EGraph<L, A>, eclass: Id) -> Option<SearchMatches<L>> {
+        let matches = self.pat.search_eclass(egraph, eclass)?;
+        let found = self.found(egraph);
+        self.keep(matches, &found)
+    }
+    // The guards are only searched once
+    fn search(&self, egraph: &EGraph<L, A>) -> Vec<SearchMatches<L>> {
+        let found = self.found(egraph);
+        self.pat
+            .search(egraph)
+            .into_iter()
+            .filter_map(|m| self.keep(m, &found))
+            .collect()
+    }
+    fn vars(&self) -> Vec<egg::Var> {
+        self.pat.vars()
+    }
+}
+
 impl<N, L, A> Applier<L, N> for MultiPattern<A>
 where
     L: Language,
@@ -242,6 +762,26 @@ where
                 }
                 added
             }
+            // Union everything into the first term
+            MultiEq(ps) => {
+                let mut added = vec![];
+                for mat in matches {
+                    for subst in &mat.substs {
+                        for p in &ps[1..] {
+                            let (to, did_something) = egraph.union_instantiations(
+                                &ps[0].ast,
+                                &p.ast,
+                                subst,
+                                rule_name.clone(),
+                            );
+                            if did_something {
+                                added.push(to)
+                            }
+                        }
+                    }
+                }
+                added
+            }
         }
     }
 
@@ -253,6 +793,7 @@ where
                 vars.extend(r.vars());
                 vars
             }
+            MultiEq(ps) => ps.iter().flat_map(|p| p.vars()).collect(),
         }
     }
 }
@@ -342,41 +883,104 @@ where
     }
 }
 
-type SymExpr = RecExpr<SymbolLang>;
-type SymEGraph = EGraph<SymbolLang, ()>;
+type LogExpr = RecExpr<EggLog>;
+type LogEGraph = EGraph<EggLog, ConstFold>;
 
-fn simplify(egraph: &SymEGraph, eid: Id) -> SymExpr {
+fn simplify(egraph: &LogEGraph, eid: Id) -> LogExpr {
     let extractor = Extractor::new(egraph, AstSize);
     let (_best_cost, best) = extractor.find_best(eid);
     best
 }
 
+// s-expression printing, except that declared operators are printed infix.
+// Operator applications inside an s-expression are always parenthesized.
+fn show_expr(expr: &LogExpr) -> String {
+    fn worker(expr: &LogExpr, id: Id, max: usize) -> String {
+        let (op, children) = match &expr[id] {
+            EggLog::Fun(op, children) => (op.as_str(), children),
+            // A rational reads back as a division and a negative number as a negation.
+            EggLog::Lit(l) => {
+                let s = l.to_string();
+                let p = match l {
+                    Literal::Rat(_) => 400,
+                    _ if s.starts_with('-') => 200,
+                    _ => 0,
+                };
+                return if p > max { format!("({})", s) } else { s };
+            }
+        };
+        let name = || {
+            if !op.is_empty() && op.chars().all(is_symbol_char) {
+                op.to_string()
+            } else {
+                quote_atom(op)
+            }
+        };
+        let arg = |i: usize, max: usize| worker(expr, children[i], max);
+        let (s, p) = match (children.len(), infix_op(op), prefix_op(op), postfix_op(op)) {
+            (0, _, _, _) => return name(),
+            (2, Some((p, typ)), _, _) => {
+                let (lmax, rmax) = match typ {
+                    OpType::Xfy => (p - 1, p),
+                    OpType::Yfx => (p, p - 1),
+                    _ => (p - 1, p - 1),
+                };
+                (format!("{} {} {}", arg(0, lmax), op, arg(1, rmax)), p)
+            }
+            (1, _, Some((p, typ)), _) => {
+                let a = arg(0, if typ == OpType::Fy { p } else { p - 1 });
+                // Keep the operator from gluing onto the argument, as in `- -x` or `not x`.
+                let glued = op.chars().all(is_symbol_char)
+                    && !a.starts_with(is_symbol_char)
+                    && !a.starts_with('(');
+                (format!("{}{}{}", op, if glued { "" } else { " " }, a), p)
+            }
+            (1, _, _, Some((p, typ))) => {
+                let a = arg(0, if typ == OpType::Yf { p } else { p - 1 });
+                (format!("{} {}", a, op), p)
+            }
+            (n, _, _, _) => {
+                let args: String = (0..n).map(|i| format!(" {}", arg(i, 0))).collect();
+                return format!("({}{})", name(), args);
+            }
+        };
+        if p > max {
+            format!("({})", s)
+        } else {
+            s
+        }
+    }
+    worker(expr, (expr.as_ref().len() - 1).into(), 1200)
+}
+
 fn print_subst<T: std::fmt::Write>(
     buf: &mut T,
-    egraph: &EGraph<SymbolLang, ()>,
+    egraph: &LogEGraph,
     subst: &Subst,
 ) -> Result<(), std::fmt::Error> {
     write!(buf, "[");
-    let mut iter = subst.vec.iter();
+    // Variables starting with _ are not reported
+    let mut iter = subst
+        .vec
+        .iter()
+        .filter(|(k, _)| !k.to_string().starts_with("?_"));
     if let Some((k, eid)) = iter.next() {
         let best = simplify(egraph, *eid);
-        write!(buf, "{} = {}", k, best)?;
+        write!(buf, "{} = {}", k, show_expr(&best))?;
         for (k, eid) in iter {
             let best = simplify(egraph, *eid);
-            write!(buf, ", {} = {}", k, best)?;
+            write!(buf, ", {} = {}", k, show_expr(&best))?;
         }
     }
     writeln!(buf, "];")
 }
 
-type SymMultiPattern = MultiPattern<EqWrap<Pattern<SymbolLang>>>;
-
 // Current directory and already included set?
 #[derive(Debug)]
 pub struct Env {
-    runner: Runner<SymbolLang, ()>,
-    rules: Vec<egg::Rewrite<SymbolLang, ()>>,
-    queries: Vec<MultiPattern<EqWrap<Pattern<SymbolLang>>>>,
+    runner: Runner<EggLog, ConstFold>,
+    rules: Vec<egg::Rewrite<EggLog, ConstFold>>,
+    queries: Vec<MultiPattern<EqWrap<Pattern<EggLog>>>>,
 }
 
 impl Default for Env {
@@ -390,25 +994,132 @@ impl Default for Env {
 }
 
 use std::fs;
-/* // For use in the include directive
-fn load_file(env: &mut Env, filename: &str) -> Result<(), String> {
-    match fs::read_to_string(filename) {
-        Err(e) => Err(format!("Error: file {} not found", filename)),
-        Ok(contents) => match parse_file(contents) {
-            Err(e) => Err(format!(
-                "Error : file {} failed to parse with error : {}",
-                filename, e
-            )),
-            Ok(entries) => {
-                for entry in entries {
-                    process_entry(env, entry);
+use std::path::{Path, PathBuf};
+
+/// Bookkeeping for `:- include(file).` directives.
+#[derive(Debug, Clone, Default)]
+pub struct Includes {
+    search_path: Vec<PathBuf>, // -I flags, then EGGLOG_PATH
+    loaded: HashSet<PathBuf>,  // canonical paths. Every file is loaded at most once.
+    stack: Vec<PathBuf>,       // files currently being loaded, innermost last.
+}
+
+impl Includes {
+    pub fn new(opts: &Opts) -> Self {
+        let mut search_path: Vec<PathBuf> = opts.include.iter().map(PathBuf::from).collect();
+        if let Some(paths) = std::env::var_os("EGGLOG_PATH") {
+            search_path.extend(std::env::split_paths(&paths));
+        }
+        let mut includes = Includes {
+            search_path,
+            ..Includes::default()
+        };
+        // The toplevel file counts as loaded, so including it again is a cycle.
+        if let Some(path) = opts
+            .filename
+            .as_ref()
+            .and_then(|f| fs::canonicalize(f).ok())
+        {
+            includes.loaded.insert(path.clone());
+            includes.stack.push(path);
+        }
+        includes
+    }
+
+    // Relative to the including file first, then the search path, then the working directory.
+    fn resolve(&self, filename: &str) -> Option<PathBuf> {
+        let path = Path::new(filename);
+        if path.is_absolute() {
+            return Some(path.to_path_buf()).filter(|p| p.is_file());
+        }
+        let current_dir = self.stack.last().and_then(|f| f.parent());
+        current_dir
+            .into_iter()
+            .chain(self.search_path.iter().map(PathBuf::as_path))
+            .chain(std::iter::once(Path::new(".")))
+            .map(|dir| dir.join(path))
+            .find(|p| p.is_file())
+    }
+
+    // Declares the operators of an included file and the files it includes, without running anything.
+    // Missing files and syntax errors are left for whoever runs the program to report.
+    fn declare_ops(&mut self, filename: &str) {
+        let path = match self
+            .resolve(filename)
+            .and_then(|p| fs::canonicalize(p).ok())
+        {
+            Some(path) => path,
+            None => return,
+        };
+        if self.stack.contains(&path) || !self.loaded.insert(path.clone()) {
+            return;
+        }
+        if let Ok(contents) = fs::read_to_string(&path) {
+            self.stack.push(path);
+            for entry in included_entries(&contents) {
+                if let Ok(Directive(types::Directive::Include(f))) = entry {
+                    self.declare_ops(&f);
                 }
-                Ok(())
             }
-        },
+            self.stack.pop();
+        }
+    }
+}
+
+// For use in the include directive
+fn load_file(prog: &mut Program, filename: &str) -> Result<(), String> {
+    let path = prog
+        .includes
+        .resolve(filename)
+        .and_then(|p| fs::canonicalize(p).ok())
+        .ok_or_else(|| format!("file {} not found", filename))?;
+    if prog.includes.stack.contains(&path) {
+        let cycle: Vec<String> = prog
+            .includes
+            .stack
+            .iter()
+            .skip_while(|p| **p != path)
+            .chain(std::iter::once(&path))
+            .map(|p| p.display().to_string())
+            .collect();
+        return Err(format!("include cycle {}", cycle.join(" -> ")));
+    }
+    if !prog.includes.loaded.insert(path.clone()) {
+        return Ok(());
+    }
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("could not read file {} : {}", path.display(), e))?;
+    let filename = path.display().to_string();
+    // :- ruleset(..) in an included file doesn't carry over to the rest of this one
+    let ruleset = prog.ruleset.clone();
+    prog.includes.stack.push(path);
+    let res = load_source(prog, &contents, Some(&filename));
+    prog.includes.stack.pop();
+    prog.ruleset = ruleset;
+    res
+}
+
+// Entries are processed as soon as they are parsed, so operators declared in an
+// included file are known for the rest of the including file.
+fn load_source(prog: &mut Program, contents: &str, filename: Option<&str>) -> Result<(), String> {
+    let mut errors = vec![];
+    for entry in included_entries(contents) {
+        match entry {
+            Ok(entry) => process_entry_prog(prog, entry)?,
+            Err(e) => errors.push(e),
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        let errors = SyntaxErrors(errors);
+        Err(match filename {
+            Some(filename) => errors.in_file(filename),
+            None => errors,
+        }
+        .to_string())
     }
 }
-*/
 
 use std::collections::HashSet;
 #[derive(Debug, PartialEq, Clone)]
@@ -430,6 +1141,7 @@ impl Env2 {
 fn interp_term(env: &Env2, t: &Term) -> Term {
     match t {
         Var(x) => panic!("Impossible"), // should parse formula at groundterms.
+        Lit(l) => Lit(l.clone()),
         Apply(f, args) => {
             if args.len() == 0 && env.freshvars.contains(f) {
                 Var(f.clone())
@@ -447,12 +1159,14 @@ fn interp_eqwrap(env: &Env2, t: &EqWrap<Term>) -> EqWrap<Term> {
     match t {
         Eq(a, b) => Eq(interp_term(env, a), interp_term(env, b)),
         Bare(a) => Bare(interp_term(env, a)),
+        MultiEq(ts) => MultiEq(ts.iter().map(|t| interp_term(env, t)).collect()),
     }
 }
 
 fn interp_term_goal(env: &Env2, t: &Term) -> Term {
     match t {
         Var(x) => panic!("Impossible"),
+        Lit(l) => Lit(l.clone()),
         Apply(f, args) => {
             if args.len() == 0 && env.metavars.contains(f) {
                 Var(f.clone())
@@ -470,41 +1184,502 @@ fn interp_eqwrap_goal(env: &Env2, t: &EqWrap<Term>) -> EqWrap<Term> {
     match t {
         Eq(a, b) => Eq(interp_term_goal(env, a), interp_term_goal(env, b)),
         Bare(a) => Bare(interp_term_goal(env, a)),
+        MultiEq(ts) => MultiEq(ts.iter().map(|t| interp_term_goal(env, t)).collect()),
     }
 }
 use Formula::*;
 // We shouldn't be using mutable envs. What am I thinking?
 
-/*
-More imperative style to a program?
+// The file in order, each step with the number of facts declared before it.
+// A file without any Run, Push, Pop or Clear is run once before all queries.
+#[derive(Debug, Clone)]
 enum SearchProgram {
-    Run,
+    Run(Option<usize>, Vec<usize>), // iterations (None for the iter limit), rules in scope
+    Schedule(Schedule<Vec<usize>>),
+    Query(Option<(Vec<LogExpr>, Vec<usize>)>), // terms to add and rules to run first, for ?+
+    Hypothetical(Vec<(LogExpr, LogExpr)>, Vec<usize>), // facts and rules of |- A => B
+    Push,
+    Pop,
     Clear,
 }
-*/
 
-// Module?
-#[derive(Debug, Clone)]
-pub struct Program {
-    // eqfacts and facts, or just duplicate for base facts?
-    facts: Vec<(RecExpr<SymbolLang>, RecExpr<SymbolLang>)>,
-    rules: Vec<egg::Rewrite<SymbolLang, ()>>,
-    queries: Vec<MultiPattern<EqWrap<Pattern<SymbolLang>>>>,
+// The symbols at the top of the terms a rule adds and looks for. Negation is stratified by these.
+#[derive(Debug, Clone, Default)]
+struct Deps {
+    heads: Vec<String>,
+    pos: Vec<String>,
+    neg: Vec<String>,
+    merges: bool, // unions eclasses, so it can make anything true of a class
 }
 
-impl Default for Program {
-    fn default() -> Self {
-        Program {
-            facts: vec![],
-            queries: vec![],
-            rules: vec![],
+impl Deps {
+    fn new<'a>(
+        heads: impl IntoIterator<Item = &'a Pattern<EggLog>>,
+        pos: impl IntoIterator<Item = &'a Pattern<EggLog>>,
+        neg: impl IntoIterator<Item = &'a Pattern<EggLog>>,
+    ) -> Self {
+        fn tops<'a>(ps: impl IntoIterator<Item = &'a Pattern<EggLog>>) -> Vec<String> {
+            ps.into_iter()
+                .filter_map(|p| match p.ast.as_ref().last() {
+                    Some(ENodeOrVar::ENode(EggLog::Fun(f, _))) => Some(f.to_string()),
+                    _ => None,
+                })
+                .collect()
+        }
+        Deps {
+            heads: tops(heads),
+            pos: tops(pos),
+            neg: tops(neg),
+            merges: false,
+        }
+    }
+
+    // Rewrites, and rules with = in the head
+    fn merging(self, merges: bool) -> Self {
+        Deps { merges, ..self }
+    }
+
+    // The symbols a body looks at, and the ones it negates
+    fn add_conds(&mut self, body: &[Cond]) {
+        let tops = |e: &EqWrap<Term>| -> Vec<String> {
+            e.terms()
+                .into_iter()
+                .filter_map(|t| match t {
+                    Apply(f, _) => Some(f.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        for cond in body {
+            match cond {
+                Cond::Pos(e) => self.pos.extend(tops(e)),
+                Cond::Not(e) => self.neg.extend(tops(e)),
+                Cond::Or(branches) => branches.iter().for_each(|b| self.add_conds(b)),
+            }
+        }
+    }
+}
+
+fn eq_patterns(ps: &[EqWrap<Pattern<EggLog>>]) -> impl Iterator<Item = &Pattern<EggLog>> {
+    ps.iter().flat_map(|p| p.terms())
+}
+
+// Module?
+#[derive(Debug, Clone)]
+pub struct Program {
+    // eqfacts and facts, or just duplicate for base facts?
+    facts: Vec<(LogExpr, LogExpr)>,
+    rules: Vec<egg::Rewrite<EggLog, ConstFold>>,
+    rule_info: Vec<(String, String)>, // name given to :- disable, ruleset
+    deps: Vec<Deps>,
+    rule_names: HashSet<String>,
+    naming: Option<String>, // name for the rules of the current entry
+    ruleset: String,
+    backoff: HashMap<String, (Option<usize>, Option<usize>)>, // match limit, ban length
+    queries: Vec<LogBody>,
+    goals: Vec<Proof>,                   // goals that were taken apart
+    unique: HashMap<usize, Vec<String>>, // exists! queries, with the variables that need one answer
+    steps: Vec<(usize, SearchProgram)>,
+    hidden: HashSet<usize>,               // rules dropped by pop or clear
+    scopes: Vec<(usize, HashSet<usize>)>, // rules and hidden at each push
+    includes: Includes,
+    // Set by :- node_limit(N). and friends
+    node_limit: usize,
+    iter_limit: usize,
+    time_limit: f64, // seconds
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Program {
+            facts: vec![],
+            queries: vec![],
+            goals: vec![],
+            unique: HashMap::new(),
+            rules: vec![],
+            rule_info: vec![],
+            deps: vec![],
+            rule_names: HashSet::new(),
+            naming: None,
+            ruleset: "default".to_string(),
+            backoff: HashMap::new(),
+            steps: vec![],
+            hidden: HashSet::new(),
+            scopes: vec![],
+            includes: Includes::default(),
+            node_limit: 10_000,
+            iter_limit: 30,
+            time_limit: 5.0,
         }
     }
 }
 
-pub fn process_entry_prog(prog: &mut Program, entry: Entry) {
+// Duration::from_secs_f64 panics on a time limit that is negative, NaN or too big
+pub fn check_secs(secs: f64) -> Result<f64, String> {
+    if secs.is_finite() && secs >= 0.0 && secs < u64::MAX as f64 {
+        Ok(secs)
+    } else {
+        Err(format!("{} is not a time limit in seconds", secs))
+    }
+}
+
+fn parse_secs(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .map_err(|e| format!("{}: {}", s, e))
+        .and_then(check_secs)
+}
+
+impl Program {
+    // The limits from the file, unless given on the command line. The time limit is for the
+    // whole program, so each Runner only gets what is left before `deadline`. The node limit
+    // already is, since every Runner gets the egraph the one before it grew.
+    fn runner(&self, opts: &Opts, deadline: Option<Instant>) -> Runner<EggLog, ConstFold> {
+        let time = match deadline {
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+            None => Duration::from_secs_f64(opts.time_limit.unwrap_or(self.time_limit)),
+        };
+        Runner::default()
+            .with_iter_limit(opts.iter_limit.unwrap_or(self.iter_limit))
+            .with_node_limit(opts.node_limit.unwrap_or(self.node_limit))
+            .with_time_limit(time)
+    }
+
+    // None when the time limit is too long to be an Instant, as good as no deadline at all
+    fn deadline(&self, opts: &Opts) -> Option<Instant> {
+        let secs = opts.time_limit.unwrap_or(self.time_limit);
+        Instant::now().checked_add(Duration::from_secs_f64(secs))
+    }
+
+    fn push_query(&mut self, q: LogBody) {
+        self.queries.push(q);
+        self.push_step(SearchProgram::Query(None));
+    }
+
+    // ?+ seeds the egraph with the ground parts of the query and runs the rules before asking.
+    // The seeds belong to the step rather than the facts, so a file without any :- run.
+    // runs everything else first without them.
+    fn push_insert_query(&mut self, qs: &[EqWrap<Term>]) {
+        let mut seeds = vec![];
+        for q in qs {
+            for t in q.terms() {
+                ground_subterms(t, &mut seeds);
+            }
+        }
+        let seeds = seeds.iter().map(recexpr_of_groundterm).collect();
+        let qs = qs.iter().map(pattern_of_eqterm).collect();
+        self.queries.push(LogBody::of_patterns(qs));
+        let rules = self.in_scope(|_| true);
+        self.push_step(SearchProgram::Query(Some((seeds, rules))));
+    }
+
+    // A goal with hypotheses is asked of a copy of the egraph with the hypotheses added
+    fn push_hypothetical(&mut self, hyps: Vec<(LogExpr, LogExpr)>, q: LogBody) {
+        self.queries.push(q);
+        let rules = self.in_scope(|_| true);
+        self.push_step(SearchProgram::Hypothetical(hyps, rules));
+    }
+
+    fn push_run(&mut self, iters: Option<usize>) {
+        let rules = self.in_scope(|_| true);
+        self.push_step(SearchProgram::Run(iters, rules));
+    }
+
+    // Rules are named after the entry they come from, or `auto` when it has no name.
+    // Later rules with the same name get a #2, #3...
+    fn add_rule(
+        &mut self,
+        auto: String,
+        searcher: impl Searcher<EggLog, ConstFold> + Send + Sync + 'static,
+        applier: impl Applier<EggLog, ConstFold> + Send + Sync + 'static,
+        deps: Deps,
+    ) -> Result<(), String> {
+        let base = self.naming.clone().unwrap_or(auto);
+        let mut name = base.clone();
+        for i in 2.. {
+            if !self.rule_names.contains(&name) {
+                break;
+            }
+            name = format!("{}#{}", base, i);
+        }
+        self.rules
+            .push(egg::Rewrite::new(name.clone(), searcher, applier)?);
+        self.rule_names.insert(name);
+        self.rule_info.push((base, self.ruleset.clone()));
+        self.deps.push(deps);
+        self.stratify(&self.in_scope(|_| true)).map(|_| ())
+    }
+
+    // The rules split into strata, lowest first. A rule with `\+ p` goes in a later stratum
+    // than every rule that adds a p, so p has saturated before it is negated.
+    fn stratify(&self, rules: &[usize]) -> Result<Vec<Vec<usize>>, String> {
+        if rules.iter().all(|&i| self.deps[i].neg.is_empty()) {
+            return Ok(vec![rules.to_vec()]);
+        }
+        // A rewrite guarded by a \+ (anything that merges and negates) would be in a cycle
+        // with its own merges below, so those run in a last stratum once the rest has
+        // saturated, along with every rule that looks for what they add. What a rule adds
+        // is what it doesn't already look for, so `a <- b` adds a but not b.
+        let adds = |i: usize| {
+            let deps = &self.deps[i];
+            deps.heads.iter().filter(move |h| !deps.pos.contains(h))
+        };
+        let mut last: HashSet<usize> = rules
+            .iter()
+            .copied()
+            .filter(|&i| self.deps[i].merges && !self.deps[i].neg.is_empty())
+            .collect();
+        let mut grew = !last.is_empty();
+        while grew {
+            let added: HashSet<&str> = last
+                .iter()
+                .flat_map(|&i| adds(i).map(String::as_str))
+                .collect();
+            let more: Vec<usize> = rules
+                .iter()
+                .copied()
+                .filter(|i| !last.contains(i))
+                .filter(|&i| {
+                    let deps = &self.deps[i];
+                    deps.pos
+                        .iter()
+                        .chain(&deps.neg)
+                        .any(|p| added.contains(p.as_str()))
+                })
+                .collect();
+            grew = !more.is_empty();
+            last.extend(more);
+        }
+        // Nothing that runs last can add what is negated there
+        for &i in rules.iter().filter(|i| last.contains(i)) {
+            for n in &self.deps[i].neg {
+                if last.iter().any(|&j| adds(j).any(|h| h == n)) {
+                    return Err(format!(
+                        "\\+ {} is added by a rule that runs last with the rewrites guarded by \\+, in {}",
+                        n,
+                        self.rules[i].name()
+                    ));
+                }
+            }
+        }
+        let rest: Vec<usize> = rules
+            .iter()
+            .copied()
+            .filter(|i| !last.contains(i))
+            .collect();
+        let mut strata = self.stratify_rest(&rest)?;
+        if !last.is_empty() {
+            strata.retain(|s| !s.is_empty());
+            strata.push(rules.iter().copied().filter(|i| last.contains(i)).collect());
+        }
+        Ok(strata)
+    }
+
+    // The strata below the rewrites guarded by \+
+    fn stratify_rest(&self, rules: &[usize]) -> Result<Vec<Vec<usize>>, String> {
+        if rules.iter().all(|&i| self.deps[i].neg.is_empty()) {
+            return Ok(vec![rules.to_vec()]);
+        }
+        // A rule that merges eclasses can make r(X) true of a class by merging it with one
+        // that has r, so it counts as adding everything that is negated
+        let negated: Vec<&str> = rules
+            .iter()
+            .flat_map(|&i| self.deps[i].neg.iter().map(String::as_str))
+            .collect();
+        let heads = |i: usize, merged: bool| -> Vec<&str> {
+            let deps = &self.deps[i];
+            let mut heads: Vec<&str> = deps.heads.iter().map(String::as_str).collect();
+            if merged && deps.merges {
+                heads.extend(&negated);
+            }
+            heads
+        };
+        // symbol -> the symbols it depends on, and whether through a negation
+        let graph = |merged: bool| {
+            let mut edges: HashMap<&str, Vec<(&str, bool)>> = HashMap::new();
+            for &i in rules {
+                let deps = &self.deps[i];
+                let heads = heads(i, merged);
+                for h in &heads {
+                    let out = edges.entry(*h).or_default();
+                    // All the heads of a rule go in one stratum
+                    out.extend(heads.iter().map(|p| (*p, false)));
+                    out.extend(deps.pos.iter().map(|p| (p.as_str(), false)));
+                    out.extend(deps.neg.iter().map(|p| (p.as_str(), true)));
+                }
+            }
+            edges
+        };
+        let (edges, plain) = (graph(true), graph(false));
+        let reaches = |edges: &HashMap<&str, Vec<(&str, bool)>>, from: &str, to: &str| {
+            let mut seen = HashSet::new();
+            let mut todo = vec![from];
+            while let Some(s) = todo.pop() {
+                if s == to {
+                    return true;
+                }
+                if seen.insert(s) {
+                    todo.extend(edges.get(s).into_iter().flatten().map(|(d, _)| *d));
+                }
+            }
+            false
+        };
+        for &i in rules {
+            let deps = &self.deps[i];
+            for (h, n) in heads(i, true)
+                .into_iter()
+                .flat_map(|h| deps.neg.iter().map(move |n| (h, n)))
+            {
+                if reaches(&plain, n, h) && deps.heads.iter().any(|d| d == h) {
+                    return Err(format!(
+                        "\\+ {} is in a recursive cycle with {}, in {}",
+                        n,
+                        h,
+                        self.rules[i].name()
+                    ));
+                }
+                if reaches(&edges, n, h) {
+                    return Err(format!(
+                        "\\+ {} is in a recursive cycle with {}, since rules that merge eclasses (rewrites, or = in a head) count as adding every negated symbol, in {}",
+                        n,
+                        h,
+                        self.rules[i].name()
+                    ));
+                }
+            }
+        }
+        // Longest path, where a negation counts one
+        let mut level: HashMap<&str, usize> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (h, out) in &edges {
+                for (d, negated) in out {
+                    let l = level.get(d).copied().unwrap_or(0) + *negated as usize;
+                    if l > level.get(h).copied().unwrap_or(0) {
+                        level.insert(h, l);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        let mut strata = vec![vec![]; level.values().max().copied().unwrap_or(0) + 1];
+        for &i in rules {
+            let l = heads(i, true)
+                .into_iter()
+                .filter_map(|h| level.get(h))
+                .max();
+            strata[l.copied().unwrap_or(0)].push(i);
+        }
+        strata.retain(|s| !s.is_empty());
+        Ok(strata)
+    }
+
+    fn in_scope(&self, keep: impl Fn(&(String, String)) -> bool) -> Vec<usize> {
+        (0..self.rules.len())
+            .filter(|i| !self.hidden.contains(i) && keep(&self.rule_info[*i]))
+            .collect()
+    }
+
+    // By the name it was given or its full name
+    fn rules_named(&self, name: &str) -> Vec<usize> {
+        (0..self.rules.len())
+            .filter(|&i| self.rule_info[i].0 == name || self.rules[i].name() == name)
+            .collect()
+    }
+
+    fn disable(&mut self, name: &str) -> Result<(), String> {
+        let found = self.rules_named(name);
+        if found.is_empty() {
+            return Err(format!("disable: no rule named {}", name));
+        }
+        self.hidden.extend(found);
+        Ok(())
+    }
+
+    // :- match_limit(name, N). and :- ban_length(name, N).
+    fn set_backoff(
+        &mut self,
+        name: &str,
+        match_limit: Option<usize>,
+        ban_length: Option<usize>,
+    ) -> Result<(), String> {
+        let found = self.rules_named(name);
+        if found.is_empty() {
+            let directive = if match_limit.is_some() {
+                "match_limit"
+            } else {
+                "ban_length"
+            };
+            return Err(format!("{}: no rule named {}", directive, name));
+        }
+        for i in found {
+            let limits = self
+                .backoff
+                .entry(self.rules[i].name().to_string())
+                .or_default();
+            limits.0 = match_limit.or(limits.0);
+            limits.1 = ban_length.or(limits.1);
+        }
+        Ok(())
+    }
+
+    fn push_schedule(&mut self, schedule: &Schedule) -> Result<(), String> {
+        let schedule = schedule.try_map(&mut |ruleset| {
+            if self.rule_info.iter().all(|r| r.1 != *ruleset) {
+                return Err(format!("schedule: no rules in ruleset {}", ruleset));
+            }
+            Ok(self.in_scope(|r| r.1 == *ruleset))
+        })?;
+        self.push_step(SearchProgram::Schedule(schedule));
+        Ok(())
+    }
+
+    fn push_step(&mut self, step: SearchProgram) {
+        self.steps.push((self.facts.len(), step));
+    }
+
+    // :- push. and :- pop. The egraph is saved and restored when the program is run.
+    fn push_scope(&mut self) {
+        self.scopes.push((self.rules.len(), self.hidden.clone()));
+        self.push_step(SearchProgram::Push);
+    }
+
+    fn pop_scope(&mut self) -> Result<(), String> {
+        let (rules, hidden) = self.scopes.pop().ok_or("pop without a matching push")?;
+        self.hidden = hidden;
+        self.hidden.extend(rules..self.rules.len());
+        self.push_step(SearchProgram::Pop);
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.scopes.clear();
+        self.hidden.extend(0..self.rules.len());
+        self.push_step(SearchProgram::Clear);
+    }
+}
+
+pub fn process_entry_prog(prog: &mut Program, entry: Entry) -> Result<(), String> {
     match entry {
-        Directive(types::Directive::Include(filename)) => (), // load_file(state, &filename).unwrap(),
+        Directive(types::Directive::Include(filename)) => load_file(prog, &filename)?,
+        Directive(types::Directive::Op(..)) => (), // Declared by the parser
+        Directive(types::Directive::NodeLimit(n)) => prog.node_limit = n,
+        Directive(types::Directive::IterLimit(n)) => prog.iter_limit = n,
+        Directive(types::Directive::TimeLimit(secs)) => prog.time_limit = secs,
+        Directive(types::Directive::Run(iters)) => prog.push_run(iters),
+        Directive(types::Directive::Saturate) => prog.push_run(Some(usize::MAX)),
+        Directive(types::Directive::Push) => prog.push_scope(),
+        Directive(types::Directive::Pop) => prog.pop_scope()?,
+        Directive(types::Directive::Clear) => prog.clear(),
+        Directive(types::Directive::Ruleset(name)) => prog.ruleset = name,
+        Directive(types::Directive::Schedule(s)) => prog.push_schedule(&s)?,
+        Directive(types::Directive::Disable(name)) => prog.disable(&name)?,
+        Directive(types::Directive::MatchLimit(name, n)) => {
+            prog.set_backoff(&name, Some(n), None)?
+        }
+        Directive(types::Directive::BanLength(name, n)) => {
+            prog.set_backoff(&name, None, Some(n))?
+        }
         Fact(Eq(a, b)) => {
             let a = recexpr_of_groundterm(&a);
             let b = recexpr_of_groundterm(&b);
@@ -514,122 +1689,395 @@ pub fn process_entry_prog(prog: &mut Program, entry: Entry) {
             let a = recexpr_of_groundterm(&a);
             prog.facts.push((a.clone(), a))
         }
+        Fact(MultiEq(ts)) => {
+            let a = recexpr_of_groundterm(&ts[0]);
+            for b in &ts[1..] {
+                prog.facts.push((a.clone(), recexpr_of_groundterm(b)))
+            }
+        }
         Clause(head, body) => {
-            let body = body
-                .iter()
-                .map(|eqt| match eqt {
-                    Bare(p) => Bare(pattern_of_term(p)),
-                    Eq(a, b) => Eq(pattern_of_term(a), pattern_of_term(b)),
-                })
-                .collect();
-            let searcher = MultiPattern { patterns: body };
-            let head = head
-                .iter()
-                .map(|eqt| match eqt {
-                    Bare(p) => Bare(pattern_of_term(p)),
-                    Eq(a, b) => Eq(pattern_of_term(a), pattern_of_term(b)),
-                })
-                .collect();
+            let searcher = compile_body(&body)?;
+            let head: Vec<_> = head.iter().map(pattern_of_eqterm).collect();
+            let merges = head.iter().any(|h| !matches!(h, Bare(_)));
+            let mut deps = Deps::new(eq_patterns(&head), vec![], vec![]).merging(merges);
+            deps.add_conds(&body);
             let applier = MultiPattern { patterns: head };
-            prog.rules.push(
-                egg::Rewrite::new(format!("{}:-{}.", applier, searcher), searcher, applier)
-                    .unwrap(),
-            );
+            let auto = format!("{}:-{}.", applier, searcher);
+            if body.iter().all(|c| matches!(c, Cond::Pos(_))) {
+                let searcher = MultiPattern {
+                    patterns: searcher.equations().cloned().collect(),
+                };
+                prog.add_rule(auto, searcher, applier, deps)?;
+            } else {
+                prog.add_rule(auto, searcher, applier, deps)?;
+            }
         }
         BiRewrite(a, b) => {
             let a = pattern_of_term(&a);
             let b = pattern_of_term(&b);
-            prog.rules
-                .push(egg::Rewrite::new(format!("{} -> {}", a, b), a.clone(), b.clone()).unwrap());
-            prog.rules
-                .push(egg::Rewrite::new(format!("{} -> {}", b, a), b, a).unwrap());
+            let ab = Deps::new(vec![&a, &b], vec![&a], vec![]).merging(true);
+            let ba = Deps::new(vec![&a, &b], vec![&b], vec![]).merging(true);
+            prog.add_rule(format!("{} -> {}", a, b), a.clone(), b.clone(), ab)?;
+            prog.add_rule(format!("{} -> {}", b, a), b, a, ba)?;
         }
-        Rewrite(a, b, body) => {
+        Rewrite(a, b, guards) => {
             let a = pattern_of_term(&a);
             let b = pattern_of_term(&b);
+            let mut deps = Deps::new(vec![&a, &b], vec![&b], vec![]).merging(true);
+            deps.add_conds(&guards);
+            // Equations are checked when the rule is applied, the rest while searching
+            let mut body = vec![];
+            let mut negs = vec![];
+            let mut alts = vec![];
+            for cond in &guards {
+                match cond {
+                    Cond::Pos(e) => body.push(e),
+                    Cond::Not(e) => negs.push(pattern_of_eqterm(e)),
+                    Cond::Or(_) => alts.push(compile_body(std::slice::from_ref(cond))?),
+                }
+            }
             // consider shortcircuiting case where body = []
             let conditions: Vec<_> = body
                 .iter()
-                .map(|e| {
-                    let (l, r) = match e {
-                        Eq(a, b) => (pattern_of_term(&a), pattern_of_term(&b)),
-                        Bare(a) => (pattern_of_term(&a), pattern_of_term(&a)),
+                .flat_map(|e| {
+                    let ts = e.terms();
+                    // Each adjacent pair in a chain, or a bare term with itself
+                    let pairs: Vec<_> = match ts.len() {
+                        1 => vec![(ts[0], ts[0])],
+                        _ => ts.windows(2).map(|w| (w[0], w[1])).collect(),
                     };
-                    ConditionEqual::new(l, r)
+                    pairs
+                        .into_iter()
+                        .map(|(a, b)| ConditionEqual::new(pattern_of_term(a), pattern_of_term(b)))
+                        .collect::<Vec<_>>()
                 })
                 .collect();
-            let condition = move |egraph: &mut EGraph<_, ()>, eclass: Id, subst: &Subst| {
+            let condition = move |egraph: &mut EGraph<_, ConstFold>, eclass: Id, subst: &Subst| {
                 conditions.iter().all(|c| c.check(egraph, eclass, subst))
             };
             let applier = ConditionalApplier {
                 condition,
                 applier: a.clone(),
             };
-            if body.len() == 0 {
-                prog.rules
-                    .push(egg::Rewrite::new(format!("{} -> {}", b, a), b, applier).unwrap());
+            if !negs.is_empty() || !alts.is_empty() {
+                let searcher = NegPattern {
+                    pat: GuardPattern {
+                        pat: b,
+                        guards: alts,
+                    },
+                    negs,
+                };
+                let auto = format!("{} -{:?}> {}", searcher, body, a);
+                prog.add_rule(auto, searcher, applier, deps)?;
+            } else if body.len() == 0 {
+                prog.add_rule(format!("{} -> {}", b, a), b, applier, deps)?;
             } else {
-                prog.rules.push(
-                    egg::Rewrite::new(format!("{} -{:?}> {}", b, body, a), b, applier).unwrap(),
-                );
+                prog.add_rule(format!("{} -{:?}> {}", b, body, a), b, applier, deps)?;
             }
         }
-        Query(qs) => {
-            let qs = qs.iter().map(pattern_of_eqterm).collect();
-            prog.queries.push(MultiPattern { patterns: qs });
+        Query(qs) => prog.push_query(compile_body(&qs)?),
+        InsertQuery(qs) => prog.push_insert_query(&qs),
+        Axiom(name, f) => {
+            prog.naming = Some(name);
+            let res = interp_formula(prog, &mut Env2::new(), f);
+            prog.naming = None;
+            res?
+        }
+        Goal(f) => interp_goal(prog, &mut Env2::new(), f)?,
+        Named(name, rule) => {
+            prog.naming = Some(name);
+            let res = process_entry_prog(prog, *rule);
+            prog.naming = None;
+            res?
         }
-        Axiom(_name, f) => interp_formula(prog, &mut Env2::new(), f), // I should use the name
-        Goal(f) => interp_goal(prog, &mut Env2::new(), f),
     }
+    Ok(())
 }
 
-// run_program with default Runner
-fn run_program2(prog: &Program) -> Vec<Vec<Subst>> {
-    let runner = Runner::default()
-        .with_iter_limit(30)
-        .with_node_limit(10_000)
-        .with_time_limit(Duration::from_secs(5));
-    let (_runner, res) = run_program(prog, runner);
+// run_program with the program's Runner
+fn run_program2(prog: &Program, opts: &Opts) -> Vec<Vec<Subst>> {
+    let mut res = vec![];
+    let bans = Bans::default();
+    run_program(prog, opts, &bans, Runner::default(), |_, _, substs| {
+        res.push(substs)
+    });
     res
 }
 
+// The biggest pieces of t without variables
+fn ground_subterms(t: &Term, out: &mut Vec<GroundTerm>) {
+    match (is_ground(t), t) {
+        (Some(g), _) => out.push(g),
+        (None, Apply(_, args)) => args.iter().for_each(|a| ground_subterms(a, out)),
+        (None, _) => (),
+    }
+}
+
+fn ground_pattern(expr: &LogExpr) -> PatternAst<EggLog> {
+    let mut ast = RecExpr::default();
+    for node in expr.as_ref() {
+        ast.add(ENodeOrVar::ENode(node.clone()));
+    }
+    ast
+}
+
+// Steps through the program, calling on_query with each query's matches
+// against the egraph at that point.
 fn run_program(
     prog: &Program,
-    mut runner: Runner<SymbolLang, ()>,
-) -> (Runner<SymbolLang, ()>, Vec<Vec<Subst>>) {
-    let egraph = &mut runner.egraph;
-    for (a, b) in &prog.facts {
-        //let a_id = egraph.add_expr(&a);
-        //let b_id = egraph.add_expr(&b);
-        let a = a.to_string().parse().unwrap();
-        let b = b.to_string().parse().unwrap();
-        egraph.union_instantiations(&a, &b, &Subst::with_capacity(0), Arc::from("Base Fact"));
-    }
-    let runner = runner.run(&prog.rules);
-    let res = prog
-        .queries
+    opts: &Opts,
+    bans: &Bans,
+    mut runner: Runner<EggLog, ConstFold>,
+    mut on_query: impl FnMut(&mut Runner<EggLog, ConstFold>, &LogBody, Vec<Subst>),
+) -> Runner<EggLog, ConstFold> {
+    let implicit: Vec<(usize, SearchProgram)>;
+    let steps = if prog
+        .steps
         .iter()
-        .map(|q| {
-            let matches = q.search(&runner.egraph);
-            matches.into_iter().flat_map(|mat| mat.substs).collect()
+        .any(|(_, s)| !matches!(s, SearchProgram::Query(_) | SearchProgram::Hypothetical(..)))
+    {
+        &prog.steps
+    } else {
+        let facts = prog.facts.len();
+        let rules = prog.in_scope(|_| true);
+        implicit = std::iter::once((facts, SearchProgram::Run(None, rules)))
+            .chain(prog.steps.iter().map(|(_, s)| (facts, s.clone())))
+            .collect();
+        &implicit
+    };
+    let deadline = prog.deadline(opts);
+    let empty = runner.egraph.clone();
+    let mut saved = vec![];
+    let mut added = 0;
+    let mut queries = prog.queries.iter();
+    for (i, (facts, step)) in steps.iter().enumerate() {
+        // Facts in a scope that is popped before anything looks at them are skipped
+        if !matches!(step, SearchProgram::Pop | SearchProgram::Clear) {
+            for (a, b) in &prog.facts[added..*facts] {
+                //let a_id = egraph.add_expr(&a);
+                //let b_id = egraph.add_expr(&b);
+                let a = ground_pattern(a);
+                let b = ground_pattern(b);
+                runner.egraph.union_instantiations(
+                    &a,
+                    &b,
+                    &Subst::with_capacity(0),
+                    Arc::from("Base Fact"),
+                );
+            }
+        }
+        added = *facts;
+        let pending = steps[i + 1..]
+            .iter()
+            .take_while(|(_, s)| matches!(s, SearchProgram::Query(None)))
+            .count();
+        let ctx = Ctx {
+            prog,
+            opts,
+            bans,
+            goals: early_goals(prog, opts, queries.as_slice(), pending),
+            deadline,
+        };
+        match step {
+            SearchProgram::Run(iters, rules) => runner = ctx.run_rules(runner, *iters, rules),
+            SearchProgram::Schedule(schedule) => runner = ctx.run_schedule(runner, schedule),
+            SearchProgram::Query(first) => {
+                if let Some((seeds, rules)) = first {
+                    for t in seeds {
+                        runner.egraph.add_expr(t);
+                    }
+                    let ctx = Ctx {
+                        goals: early_goals(prog, opts, queries.as_slice(), 1),
+                        ..ctx
+                    };
+                    runner = ctx.run_rules(runner, None, rules);
+                }
+                runner.egraph.rebuild();
+                let q = queries.next().unwrap();
+                let matches = q.search(&runner.egraph);
+                let substs = matches.into_iter().flat_map(|mat| mat.substs).collect();
+                on_query(&mut runner, q, substs);
+            }
+            SearchProgram::Hypothetical(hyps, rules) => {
+                runner.egraph.rebuild();
+                let before = runner.egraph.clone();
+                for (a, b) in hyps {
+                    runner.egraph.union_instantiations(
+                        &ground_pattern(a),
+                        &ground_pattern(b),
+                        &Subst::with_capacity(0),
+                        Arc::from("Hypothesis"),
+                    );
+                }
+                let ctx = Ctx {
+                    goals: early_goals(prog, opts, queries.as_slice(), 1),
+                    ..ctx
+                };
+                runner = ctx.run_rules(runner, None, rules);
+                runner.egraph.rebuild();
+                let q = queries.next().unwrap();
+                let matches = q.search(&runner.egraph);
+                let substs = matches.into_iter().flat_map(|mat| mat.substs).collect();
+                on_query(&mut runner, q, substs);
+                runner.egraph = before;
+            }
+            SearchProgram::Push => {
+                runner.egraph.rebuild();
+                saved.push(runner.egraph.clone());
+            }
+            SearchProgram::Pop => runner.egraph = saved.pop().unwrap(),
+            SearchProgram::Clear => {
+                saved.clear();
+                runner.egraph = empty.clone();
+            }
+        }
+    }
+    runner
+}
+
+// What the Runners for one step of the program share
+// The next n queries, for the run before them to stop once they are proven. Not if one has
+// variables to answer, since the answers found by then could be fewer or bigger terms,
+// or if one is an exists! goal, since any later match could be a second witness.
+// --all-answers and --report always run to the limits.
+fn early_goals<'a>(prog: &Program, opts: &Opts, queries: &'a [LogBody], n: usize) -> &'a [LogBody] {
+    let next = prog.queries.len() - queries.len();
+    // A negated condition can stop holding once more is derived, so only queries
+    // without one are sure to stay proven
+    let ground = |q: &LogBody| {
+        !q.negates()
+            && Searcher::<EggLog, ConstFold>::vars(q)
+                .iter()
+                .all(|v| v.to_string().starts_with("?_"))
+    };
+    if opts.all_answers
+        || opts.report
+        || (next..next + n).any(|i| prog.unique.contains_key(&i))
+        || !queries[..n].iter().all(ground)
+    {
+        &[]
+    } else {
+        &queries[..n]
+    }
+}
+
+struct Ctx<'a> {
+    prog: &'a Program,
+    opts: &'a Opts,
+    bans: &'a Bans,
+    goals: &'a [LogBody], // the queries right after this step
+    deadline: Option<Instant>,
+}
+
+impl Ctx<'_> {
+    // Once every goal is proven there is no need to keep going
+    fn proven(&self, egraph: &LogEGraph) -> bool {
+        !self.goals.is_empty() && self.goals.iter().all(|q| !q.search(egraph).is_empty())
+    }
+
+    // One stratum after another, each for up to `iters` iterations
+    fn run_rules(
+        &self,
+        runner: Runner<EggLog, ConstFold>,
+        iters: Option<usize>,
+        rules: &[usize],
+    ) -> Runner<EggLog, ConstFold> {
+        let strata = self
+            .prog
+            .stratify(rules)
+            .expect("rules are stratified as they are added");
+        strata.iter().fold(runner, |runner, rules| {
+            self.run_stratum(runner, iters, rules)
         })
-        .collect();
-    (runner, res)
+    }
+
+    // A fresh Runner each time, since a finished one stays stopped
+    fn run_stratum(
+        &self,
+        runner: Runner<EggLog, ConstFold>,
+        iters: Option<usize>,
+        rules: &[usize],
+    ) -> Runner<EggLog, ConstFold> {
+        let (prog, opts) = (self.prog, self.opts);
+        let mut next = prog.runner(opts, self.deadline);
+        if let Some(n) = iters {
+            next = next.with_iter_limit(n);
+        }
+        next = match opts.scheduler {
+            SchedulerKind::Simple => next.with_scheduler(SimpleScheduler),
+            SchedulerKind::Backoff => next.with_scheduler(
+                Backoff::new(opts.match_limit, opts.ban_length, self.bans.clone())
+                    .with_rules(prog.backoff.clone()),
+            ),
+        };
+        if !self.goals.is_empty() {
+            let goals = self.goals.to_vec();
+            next = next.with_hook(move |runner| {
+                if goals.iter().all(|q| !q.search(&runner.egraph).is_empty()) {
+                    Err("all goals proven".to_string())
+                } else {
+                    Ok(())
+                }
+            });
+        }
+        let rules = rules.iter().map(|&i| &prog.rules[i]);
+        next.with_egraph(runner.egraph).run(rules)
+    }
+
+    // Repeating or saturating a single ruleset is one Runner. Anything bigger is
+    // stepped through, and saturated when a whole pass changes nothing.
+    fn run_schedule(
+        &self,
+        mut runner: Runner<EggLog, ConstFold>,
+        schedule: &Schedule<Vec<usize>>,
+    ) -> Runner<EggLog, ConstFold> {
+        if self.proven(&runner.egraph) {
+            return runner;
+        }
+        match schedule {
+            Schedule::Run(rules) => self.run_rules(runner, Some(1), rules),
+            Schedule::Repeat(n, s) => match &**s {
+                Schedule::Run(rules) => self.run_rules(runner, Some(*n), rules),
+                s => (0..*n).fold(runner, |runner, _| self.run_schedule(runner, s)),
+            },
+            Schedule::Saturate(s) => match &**s {
+                Schedule::Run(rules) => self.run_rules(runner, Some(usize::MAX), rules),
+                s => loop {
+                    let size =
+                        |egraph: &LogEGraph| (egraph.total_size(), egraph.number_of_classes());
+                    let before = size(&runner.egraph);
+                    runner = self.run_schedule(runner, s);
+                    if size(&runner.egraph) == before {
+                        break runner;
+                    }
+                },
+            },
+            Schedule::Seq(ss) => ss
+                .iter()
+                .fold(runner, |runner, s| self.run_schedule(runner, s)),
+        }
+    }
 }
 
 use std::collections::HashMap;
 fn freshen_formula(vs: Vec<String>, f: &Formula) -> Formula {
     let mut freshmap = HashMap::new();
     for v in &vs {
-        freshmap.insert(v.clone(), gensym::gensym(&v));
+        freshmap.insert(v.clone(), Apply(gensym::gensym(&v), vec![]));
     }
-    fn freshen_term(freshmap: &HashMap<String, String>, vs: &Vec<String>, t: &Term) -> Term {
+    subst_formula(&freshmap, f)
+}
+
+// Replaces the constants named in `sub`, except under a quantifier that binds them again
+fn subst_formula(sub: &HashMap<String, Term>, f: &Formula) -> Formula {
+    fn freshen_term(freshmap: &HashMap<String, Term>, vs: &Vec<String>, t: &Term) -> Term {
         match t {
             Var(x) => Var(x.clone()),
+            Lit(l) => Lit(l.clone()),
             Apply(f, args) => {
                 if args.len() == 0 {
                     if vs.contains(f) {
-                        Apply(freshmap.get(f).unwrap().clone(), vec![])
+                        freshmap.get(f).unwrap().clone()
                     } else {
                         Apply(f.clone(), vec![])
                     }
@@ -644,7 +2092,7 @@ fn freshen_formula(vs: Vec<String>, f: &Formula) -> Formula {
             }
         }
     }
-    fn worker(fm: &HashMap<String, String>, vs: &Vec<String>, f: &Formula) -> Formula {
+    fn worker(fm: &HashMap<String, Term>, vs: &Vec<String>, f: &Formula) -> Formula {
         if vs.len() == 0 {
             f.clone()
         } else {
@@ -665,195 +2113,535 @@ fn freshen_formula(vs: Vec<String>, f: &Formula) -> Formula {
                     vs.retain(|v| !vs2.contains(v));
                     Exists(vs2.clone(), Box::new(worker(fm, &vs, &*f)))
                 }
-                Atom(t) => Atom(match t {
-                    Eq(a, b) => Eq(freshen_term(fm, vs, a), freshen_term(fm, vs, b)),
-                    Bare(a) => Bare(freshen_term(fm, vs, a)),
-                }),
+                ExistsUnique(vs2, f) => {
+                    let mut vs = vs.clone();
+                    vs.retain(|v| !vs2.contains(v));
+                    ExistsUnique(vs2.clone(), Box::new(worker(fm, &vs, &*f)))
+                }
+                Atom(t) => Atom(t.clone().map(|a| freshen_term(fm, vs, &a))),
             }
         }
     }
-    worker(&freshmap, &vs, f)
+    let vs = sub.keys().cloned().collect();
+    worker(sub, &vs, f)
 }
 
-fn interp_goal(prog: &mut Program, env: &Env2, formula: Formula) {
-    match formula {
-        Conj(fs) => {
-            let ps = fs
+// exists in what an axiom concludes is a new term for each match of the hypothesis,
+// built by a fresh #sk_y#N symbol from the variables the hypothesis binds.
+// Without a hypothesis it is just a new constant.
+fn skolemize(args: &[Term], f: Formula) -> Formula {
+    match f {
+        Exists(vs, f) => {
+            let sk = vs
                 .iter()
-                .map(|g| match g {
-                    // TODO: How to not insist on Atom here?
-                    // recurse on fresh progs, accumulate all queries into single query.
-                    // (sum of products)
-                    Atom(g) => pattern_of_eqterm(&interp_eqwrap_goal(env, g)),
-                    _ => panic!("unexpected form in goal"),
+                .map(|v| {
+                    let sk = gensym::gensym(&format!("sk_{}", v));
+                    (v.clone(), Apply(sk, args.to_vec()))
                 })
                 .collect();
-            prog.queries.push(MultiPattern { patterns: ps })
+            skolemize(args, subst_formula(&sk, &f))
         }
-        Atom(f) => {
-            let g = MultiPattern {
-                patterns: vec![pattern_of_eqterm(&interp_eqwrap_goal(env, &f))],
-            };
-            prog.queries.push(g)
-        }
-        Exists(vs, f) => {
-            let mut env = env.clone();
-            // freshen?
-            env.metavars.extend(vs); // patvars?
-            interp_goal(prog, &env, *f)
-        }
-        //Impl(hyp, conc) => {
-            //Is this right? Aren't I injecting hypotheses into the program for other queries then?
-            // These hypotheses need to be retracted.
-        //    interp_formula(prog, env, *hyp);
-        //    interp_goal(prog, env, *conc);
-        //}
-        ForAll(vs, f) => {
-            let f = freshen_formula(vs, &*f);
-            // freshvars mapping rather than doing eager freshen?
-            interp_goal(prog, env, f);
-        }
-        _ => panic!("no other goal"),
+        Conj(fs) => Conj(fs.into_iter().map(|f| skolemize(args, f)).collect()),
+        f => f,
     }
 }
 
-// I should make env immutable this is not right as is.
-// Or what is even the point of being this fancy
-// Could I implement higher order rules by extending symbollang?
-/*
-P => (Q => R) becomes
-P => x
-x /\ Q => R
-P => (Q => R) should really be P /\ Q => R ?
-
-(P => Q) => R
+// exists! x, p x is exists x, p x and a rule that any two are equal,
+// forall x #x#N, p x /\ p #x#N => x = #x#N
+fn uniqueness(vs: &[String], f: &Formula) -> Formula {
+    let others: Vec<String> = vs.iter().map(|v| gensym::gensym(v)).collect();
+    let var = |v: &String| Apply(v.clone(), vec![]);
+    let sub = vs.iter().cloned().zip(others.iter().map(var)).collect();
+    let eqs = vs
+        .iter()
+        .zip(&others)
+        .map(|(v, w)| Atom(Eq(var(v), var(w))))
+        .collect();
+    let both = Conj(vec![f.clone(), subst_formula(&sub, f)]);
+    ForAll(
+        vs.iter().chain(&others).cloned().collect(),
+        Box::new(Impl(Box::new(both), Box::new(Conj(eqs)))),
+    )
+}
 
+// The universally quantified variables in `f`, in the order they first appear
+fn bound_vars(env: &Env2, f: &Formula, out: &mut Vec<String>) {
+    fn term_vars(env: &Env2, t: &Term, out: &mut Vec<String>) {
+        if let Apply(f, args) = t {
+            if args.is_empty() && env.freshvars.contains(f) && !out.contains(f) {
+                out.push(f.clone());
+            }
+            args.iter().for_each(|a| term_vars(env, a, out));
+        }
+    }
+    match f {
+        Atom(a) => a.terms().into_iter().for_each(|t| term_vars(env, t, out)),
+        Conj(fs) | Disj(fs) => fs.iter().for_each(|f| bound_vars(env, f, out)),
+        Impl(a, b) => {
+            bound_vars(env, a, out);
+            bound_vars(env, b, out);
+        }
+        ForAll(_, f) | Exists(_, f) | ExistsUnique(_, f) => bound_vars(env, f, out),
+    }
+}
 
-*/
+// Hypotheses on the left, and what follows from them on the right
+#[derive(Debug, Clone)]
 struct Sequent {
     hyps: Vec<Formula>,
     conc: Formula, // sig : Vec<String>
 }
+
+impl fmt::Display for Sequent {
+    fn fmt(&self, buf: &mut fmt::Formatter) -> fmt::Result {
+        // A quantifier would swallow the commas after it
+        let hyps: Vec<String> = self
+            .hyps
+            .iter()
+            .map(|h| match h {
+                ForAll(..) | Exists(..) | ExistsUnique(..) => format!("({})", h),
+                h => h.to_string(),
+            })
+            .collect();
+        if hyps.is_empty() {
+            write!(buf, "|- {}", self.conc)
+        } else {
+            write!(buf, "{} |- {}", hyps.join(", "), self.conc)
+        }
+    }
+}
+
 /*
 The only obligations that can be discharged via egglog are those of the form
 ---------------------------------------- (egglog)
 atom, atom, hyp => conc, hyp => conc |- conj(q1,q2,q3), conj()
 which is indeed the form of a "Program".
 
-All other transformations should be recorded as an internal proof tree.
+All other transformations are recorded as an internal proof tree.
+*/
+#[derive(Debug, Clone)]
+struct Proof {
+    seq: Sequent,
+    step: Step,
+}
 
-enum Proof {
-    LAnd(Int, Box<Proof>, Box<Proof>),
+#[derive(Debug, Clone)]
+enum Step {
+    Query(usize),  // index into the program's queries
+    Unique(usize), // a query whose answers must all be the same
+    AndL(Box<Proof>),
+    AndR(Vec<Proof>),
+    OrR(Vec<Proof>), // any one of them will do
+    AllR(Box<Proof>),
+    ImpR(Box<Proof>),
+}
 
+impl Proof {
+    fn proven(&self, answered: &[bool]) -> bool {
+        match &self.step {
+            Step::Query(i) | Step::Unique(i) => answered[*i],
+            Step::AndR(ps) => ps.iter().all(|p| p.proven(answered)),
+            Step::OrR(ps) => ps.iter().any(|p| p.proven(answered)),
+            Step::AndL(p) | Step::AllR(p) | Step::ImpR(p) => p.proven(answered),
+        }
+    }
+
+    // The last query asked, after which the goal can be decided
+    fn last(&self) -> usize {
+        match &self.step {
+            Step::Query(i) | Step::Unique(i) => *i,
+            Step::AndR(ps) | Step::OrR(ps) => ps.iter().map(|p| p.last()).max().unwrap(),
+            Step::AndL(p) | Step::AllR(p) | Step::ImpR(p) => p.last(),
+        }
+    }
 
+    // One sequent a line, each with the rule it was taken apart by, or the query that closed it
+    fn show<T: std::fmt::Write>(
+        &self,
+        buf: &mut T,
+        queries: &[LogBody],
+        answered: &[bool],
+        depth: usize,
+    ) -> fmt::Result {
+        write!(buf, "{:width$}{}  by ", "", self.seq, width = 2 * depth)?;
+        let children = match &self.step {
+            Step::Query(i) => {
+                let verdict = if answered[*i] { "proved" } else { "unknown" };
+                return writeln!(buf, "-? {}: {}", queries[*i], verdict);
+            }
+            Step::Unique(i) => {
+                let verdict = if answered[*i] { "proved" } else { "unknown" };
+                return writeln!(buf, "-? {}, one witness: {}", queries[*i], verdict);
+            }
+            Step::AndL(p) => (vec![&**p], "/\\L"),
+            Step::AndR(ps) => (ps.iter().collect(), "/\\R"),
+            Step::OrR(ps) => (ps.iter().collect(), "\\/R"),
+            Step::AllR(p) => (vec![&**p], "forallR"),
+            Step::ImpR(p) => (vec![&**p], "=>R"),
+        };
+        writeln!(buf, "{}", children.1)?;
+        for p in children.0 {
+            p.show(buf, queries, answered, depth + 1)?;
+        }
+        Ok(())
+    }
 }
-*/
-/*
-fn proof(s : Sequent) -> Vec<Program>, Proof {
-    match s.conc {
-        Conj(fs) => fs.map(|conc| proof(Sequent{ s.hyps.clone(), conc})),
-        Disj(_) => fs.map(|conc| ),
-        ForAll(vs,f) => proof(hyps, freshen_formula(vs, f))
-        Impl(hyp,conc) => proof(hyps.push(hyp), conc )
-        Atom() => run_query( hyps, q )
+
+fn interp_goal(prog: &mut Program, env: &Env2, formula: Formula) -> Result<(), String> {
+    let seq = Sequent {
+        hyps: vec![],
+        conc: formula,
+    };
+    let proof = prove(prog, env, seq)?;
+    if !matches!(proof.step, Step::Query(_)) {
+        prog.goals.push(proof);
     }
+    Ok(())
 }
 
+// The invertible rules are applied until what is left fits the egraph. A disjunction is
+// tried one branch at a time. Below an exists the parts share variables, so they are one query.
+// Goals are the goal formulas of hereditary Harrop logic, and their hypotheses are axioms.
+fn prove(prog: &mut Program, env: &Env2, seq: Sequent) -> Result<Proof, String> {
+    if let Some(i) = seq.hyps.iter().position(|h| matches!(h, Conj(_))) {
+        let mut hyps = seq.hyps.clone();
+        if let Conj(fs) = hyps.remove(i) {
+            hyps.splice(i..i, fs);
+        }
+        let next = Sequent {
+            hyps,
+            conc: seq.conc.clone(),
+        };
+        let step = Step::AndL(Box::new(prove(prog, env, next)?));
+        return Ok(Proof { seq, step });
+    }
+    let next = |conc: Formula| Sequent {
+        hyps: seq.hyps.clone(),
+        conc,
+    };
+    let step = match seq.conc.clone() {
+        Conj(fs) if !fs.iter().all(|f| matches!(f, Atom(_))) => Step::AndR(
+            fs.into_iter()
+                .map(|f| prove(prog, env, next(f)))
+                .collect::<Result<_, _>>()?,
+        ),
+        Disj(fs) => Step::OrR(
+            fs.into_iter()
+                .map(|f| prove(prog, env, next(f)))
+                .collect::<Result<_, _>>()?,
+        ),
+        ForAll(vs, f) => {
+            let f = freshen_formula(vs, &*f);
+            // freshvars mapping rather than doing eager freshen?
+            Step::AllR(Box::new(prove(prog, env, next(f))?))
+        }
+        Impl(hyp, conc) => {
+            let mut hyps = seq.hyps.clone();
+            hyps.push(*hyp);
+            Step::ImpR(Box::new(prove(prog, env, Sequent { hyps, conc: *conc })?))
+        }
+        // A witness, and no other that isn't equal to it
+        ExistsUnique(vs, f) => {
+            let i = ask(prog, env, &seq.hyps, &Exists(vs.clone(), f))?;
+            prog.unique.insert(i, vs);
+            Step::Unique(i)
+        }
+        f => Step::Query(ask(prog, env, &seq.hyps, &f)?),
+    };
+    Ok(Proof { seq, step })
+}
 
+// A leaf of a proof as a query. With hypotheses it is asked of a copy of the egraph
+// they are added to, and the rules they make only hold for it.
+fn ask(prog: &mut Program, env: &Env2, hyps: &[Formula], conc: &Formula) -> Result<usize, String> {
+    let (facts, rules) = (prog.facts.len(), prog.rules.len());
+    for hyp in hyps {
+        interp_formula(prog, env, hyp.clone())?;
+    }
+    let conds = goal_conds(env, conc)?;
+    let q = compile_body(&conds)?;
+    if hyps.is_empty() {
+        prog.push_query(q)
+    } else {
+        // As with ?+, the ground parts of the goal are added too,
+        // or f a = f b couldn't be found from a = b.
+        let mut hyps = prog.facts.split_off(facts);
+        let mut seeds = vec![];
+        cond_subterms(&conds, &mut seeds);
+        for t in seeds {
+            let t = recexpr_of_groundterm(&t);
+            hyps.push((t.clone(), t));
+        }
+        prog.push_hypothetical(hyps, q);
+        prog.hidden.extend(rules..prog.rules.len());
+    }
+    Ok(prog.queries.len() - 1)
+}
 
-fn run_query(hyps, q) {
-    prog = Program::default()
-    while let Some(hyp) = hyps.pop() {
+// One query, with a disjunction as alternatives
+fn goal_conds(env: &Env2, formula: &Formula) -> Result<Vec<Cond>, String> {
+    match formula {
+        Atom(g) => Ok(vec![Cond::Pos(interp_eqwrap_goal(env, g))]),
+        Conj(fs) => Ok(fs
+            .iter()
+            .map(|f| goal_conds(env, f))
+            .collect::<Result<Vec<_>, _>>()?
+            .concat()),
+        Disj(fs) => Ok(vec![Cond::Or(
+            fs.iter()
+                .map(|f| goal_conds(env, f))
+                .collect::<Result<_, _>>()?,
+        )]),
+        Exists(vs, f) => {
+            let mut env = env.clone();
+            // freshen?
+            env.metavars.extend(vs.iter().cloned()); // patvars?
+            goal_conds(&env, f)
+        }
+        _ => Err(format!(
+            "{} can't be proven under an exists, which can only have atoms, /\\ and \\/ below it",
+            formula
+        )),
+    }
+}
 
+fn cond_subterms(conds: &[Cond], out: &mut Vec<GroundTerm>) {
+    for c in conds {
+        match c {
+            // Adding p(a) itself would make it true
+            Cond::Pos(Bare(Apply(_, args))) | Cond::Not(Bare(Apply(_, args))) => {
+                args.iter().for_each(|t| ground_subterms(t, out))
+            }
+            Cond::Pos(e) | Cond::Not(e) => {
+                e.terms().into_iter().for_each(|t| ground_subterms(t, out))
+            }
+            Cond::Or(branches) => branches.iter().for_each(|b| cond_subterms(b, out)),
+        }
     }
-    for hyp in hyps {
-        match hyp {
-            Atom(f) => prog.facts.push(f)
-            Conj(fs) => hyps.extend(f)  // Left And
-            ForAll(vs, f) => patvarize(vs, f) //| is_prim_rewrite(f) =>
-                          //| otherwise =>
-            Impl(a,b) | is_prim_pat(a) && is_prim_applier(b) => ReWrite
-            Impl(a,b) => proof( other_hyps, a ) and proof(hyps + b, q)
+}
+
+// I should make env immutable this is not right as is.
+// Or what is even the point of being this fancy
+// Could I implement higher order rules by extending symbollang?
+/*
+P => (Q => R) becomes
+P => x
+x /\ Q => R
+P => (Q => R) should really be P /\ Q => R ?
+
+(P => Q) => R
 
 
+*/
+// The hypothesis of an axiom as a body, with \/ as alternatives.
+// The variables of an exists are only bound for the search.
+fn hyp_conds(env: &Env2, hyp: &Formula) -> Result<Vec<Cond>, String> {
+    match hyp {
+        Atom(a) => Ok(vec![Cond::Pos(interp_eqwrap(env, a))]),
+        Conj(hyps) => Ok(hyps
+            .iter()
+            .map(|h| hyp_conds(env, h))
+            .collect::<Result<Vec<_>, _>>()?
+            .concat()),
+        Disj(hyps) => Ok(vec![Cond::Or(
+            hyps.iter()
+                .map(|h| hyp_conds(env, h))
+                .collect::<Result<_, _>>()?,
+        )]),
+        Exists(vs, f) => {
+            let mut env = env.clone();
+            env.freshvars.extend(vs.iter().cloned());
+            hyp_conds(&env, f)
         }
+        _ => Err(format!(
+            "{} can't be searched for, so it can't be the hypothesis of an axiom",
+            hyp
+        )),
     }
 }
-*/
 
-fn interp_formula(prog: &mut Program, env: &Env2, formula: Formula) {
+// Axioms are the program clauses of hereditary Harrop logic. Facts, universally quantified
+// equations as rewrites, and conjunctions of them, under forall and => with goals
+// that can be searched for as hypotheses.
+fn interp_formula(prog: &mut Program, env: &Env2, formula: Formula) -> Result<(), String> {
     match formula {
-        Atom(a) => { // I can support forall x, f x = g x as a bidirectional rule
-            match interp_eqwrap(env, &a) {
-                Eq(a, b) => {
-                    let a = recexpr_of_groundterm(&is_ground(&a).unwrap());
-                    let b = recexpr_of_groundterm(&is_ground(&b).unwrap());
-                    prog.facts.push((a, b))
+        Atom(a) => match interp_eqwrap(env, &a) {
+            Eq(a, b) if is_ground(&a).is_some() && is_ground(&b).is_some() => {
+                let a = recexpr_of_groundterm(&is_ground(&a).unwrap());
+                let b = recexpr_of_groundterm(&is_ground(&b).unwrap());
+                prog.facts.push((a, b))
+            }
+            // forall x, f x = g x is a rewrite each way the variables allow
+            Eq(a, b) => {
+                let a = pattern_of_term(&a);
+                let b = pattern_of_term(&b);
+                let vars = |p: &Pattern<EggLog>| Searcher::<EggLog, ConstFold>::vars(p);
+                let covers = |p, q| vars(q).iter().all(|v| vars(p).contains(v));
+                if !covers(&a, &b) && !covers(&b, &a) {
+                    return Err(format!(
+                        "{} = {} can't be a rewrite either way, since both sides have variables the other doesn't",
+                        a, b
+                    ));
                 }
-                Bare(a) => {
-                    let a = recexpr_of_groundterm(&is_ground(&a).unwrap());
-                    prog.facts.push((a.clone(), a))
+                if covers(&a, &b) {
+                    let deps = Deps::new(vec![&a, &b], vec![&a], vec![]).merging(true);
+                    prog.add_rule(format!("{} -> {}", a, b), a.clone(), b.clone(), deps)?;
                 }
-            };
-        }
+                if covers(&b, &a) {
+                    let deps = Deps::new(vec![&a, &b], vec![&b], vec![]).merging(true);
+                    prog.add_rule(format!("{} -> {}", b, a), b, a, deps)?;
+                }
+            }
+            Bare(a) => {
+                let a = is_ground(&a).ok_or(format!(
+                    "{} has variables, so it can't be added without a hypothesis",
+                    a
+                ))?;
+                let a = recexpr_of_groundterm(&a);
+                prog.facts.push((a.clone(), a))
+            }
+            MultiEq(ts) => {
+                let ts = ts.iter().map(is_ground).collect::<Option<Vec<_>>>();
+                let ts = ts.ok_or(format!(
+                    "{} has variables, so it can't be added without a hypothesis",
+                    a
+                ))?;
+                let a = recexpr_of_groundterm(&ts[0]);
+                for b in &ts[1..] {
+                    prog.facts.push((a.clone(), recexpr_of_groundterm(b)))
+                }
+            }
+        },
         Conj(fs) => {
             for f in fs {
-                interp_formula(prog, env, f);
+                interp_formula(prog, env, f)?;
             }
         }
-        Impl(hyp, conc) => {
-            /*
-            prog = Program::defualt();
-            let prog = interp_goal( prog, env, &hyp  );
-            assert prog.clauses == 0
-            assert prog.facts == 0
-            assert prog.queries.len() == 1
-            Rewrite(prog.queries, prog.
-            */
-            let hyps = match *hyp {
-                Atom(hyp) => vec![pattern_of_eqterm(&interp_eqwrap(env, &hyp))],
-                Conj(hyps) => hyps
-                    .iter()
-                    .map(|hyp| match hyp {
-                        Atom(hyp) => pattern_of_eqterm(&interp_eqwrap(env, hyp)),
-                        _ => panic!("invalid hyp in conj"),
-                    })
-                    .collect(),
-                _ => panic!("Invalid hyp {:?}", *hyp),
-            };
-            // I should be not duplicating code here.
-            // call interp_formula here. assert no queries only facts?
-            let concs = match *conc {
-                Atom(conc) => vec![pattern_of_eqterm(&interp_eqwrap(env, &conc))],
-                Conj(concs) => concs
-                    .iter()
-                    .map(|conc| match conc {
-                        Atom(conc) => pattern_of_eqterm(&interp_eqwrap(env, conc)),
-                        _ => panic!("invalid conc in conj"),
-                    })
-                    .collect(),
-                _ => panic!("Invalid conc {:?}", *conc),
-            };
-            let searcher = MultiPattern { patterns: hyps };
-            let applier = MultiPattern { patterns: concs };
-            prog.rules
-                .push(egg::Rewrite::new("", searcher, applier).unwrap())
-        }
-        // Exists in conclusion. Skolemized on freshvars?
-        // We can't allow unguarded exists though. uh. Yes we can.
-        //Exists(vs, f) => {
-            // freshvars is a bad name
-       //     let freshvars = vs.map(|v| Apply(gensym(v), env.freshvars );
-
-        //}
+        Impl(hyp, conc) => interp_clause(prog, env, &[*hyp], *conc)?,
+        Exists(..) if env.freshvars.is_empty() => {
+            interp_formula(prog, env, skolemize(&[], formula))?
+        }
+        ExistsUnique(vs, f) if env.freshvars.is_empty() => {
+            interp_formula(prog, env, Exists(vs.clone(), f.clone()))?;
+            interp_formula(prog, env, uniqueness(&vs, &f))?
+        }
+        // An unguarded exists under a forall would need a rule that matches everything
+        Exists(..) | ExistsUnique(..) => {
+            return Err(format!(
+                "{} needs a hypothesis to build its Skolem terms from, as in forall x, p x => (exists y, q x y)",
+                formula
+            ))
+        }
         // Nested Programs? swaping facts and queries in some sense?
         ForAll(vs, f) => {
             let mut env = env.clone();
             env.freshvars.extend(vs); // patvars?
-            interp_formula(prog, &env, *f)
+            interp_formula(prog, &env, *f)?
+        }
+        Disj(_) => {
+            return Err(format!(
+                "{} can't be assumed, a disjunction can only be searched for",
+                formula
+            ))
         }
-        _ => panic!("unexpected formula {:?} in interp_formula", formula),
     }
+    Ok(())
+}
+
+// The rule hyps => conc. P => (Q => R) is P /\ Q => R, and a conjunction of
+// anything but atoms is a rule for each part.
+fn interp_clause(
+    prog: &mut Program,
+    env: &Env2,
+    hyps: &[Formula],
+    conc: Formula,
+) -> Result<(), String> {
+    match conc {
+        Impl(hyp, conc) => {
+            let mut hyps = hyps.to_vec();
+            hyps.push(*hyp);
+            return interp_clause(prog, env, &hyps, *conc);
+        }
+        ForAll(vs, conc) => {
+            let mut env = env.clone();
+            env.freshvars.extend(vs);
+            return interp_clause(prog, &env, hyps, *conc);
+        }
+        Conj(fs) if !fs.iter().all(|f| matches!(f, Atom(_) | Exists(..))) => {
+            for f in fs {
+                interp_clause(prog, env, hyps, f)?;
+            }
+            return Ok(());
+        }
+        ExistsUnique(vs, f) => {
+            interp_clause(prog, env, hyps, Exists(vs.clone(), f.clone()))?;
+            return interp_clause(prog, env, hyps, uniqueness(&vs, &f));
+        }
+        _ => (),
+    }
+    let hyp = match hyps {
+        [hyp] => hyp.clone(),
+        _ => Conj(hyps.to_vec()),
+    };
+    let hyps = hyp_conds(env, &hyp)?;
+    let searcher = compile_body(&hyps)?;
+    // Only what every branch of a \/ binds can go in a Skolem term
+    let bound = Searcher::<EggLog, ConstFold>::vars(&searcher);
+    let mut args = vec![];
+    bound_vars(env, &hyp, &mut args);
+    let args: Vec<Term> = args
+        .into_iter()
+        .filter(|x| bound.contains(&format!("?{}", x).parse().unwrap()))
+        .map(|x| Apply(x, vec![]))
+        .collect();
+    let mut concs = vec![];
+    conc_atoms(env, &skolemize(&args, conc), &mut concs)?;
+    let merges = concs.iter().any(|c| !matches!(c, Bare(_)));
+    let mut deps = Deps::new(eq_patterns(&concs), vec![], vec![]).merging(merges);
+    deps.add_conds(&hyps);
+    let applier = MultiPattern { patterns: concs };
+    let auto = format!("{} => {}", searcher, applier);
+    for c in &applier.patterns {
+        if let Some(v) = Searcher::<EggLog, ConstFold>::vars(c)
+            .into_iter()
+            .find(|v| !bound.contains(v))
+        {
+            return Err(format!(
+                "{} uses {}, which the hypothesis doesn't bind",
+                auto, v
+            ));
+        }
+    }
+    if hyps.iter().all(|c| matches!(c, Cond::Pos(_))) {
+        let searcher = MultiPattern {
+            patterns: searcher.equations().cloned().collect(),
+        };
+        prog.add_rule(auto, searcher, applier, deps)
+    } else {
+        prog.add_rule(auto, searcher, applier, deps)
+    }
+}
+
+fn conc_atoms(
+    env: &Env2,
+    conc: &Formula,
+    out: &mut Vec<EqWrap<Pattern<EggLog>>>,
+) -> Result<(), String> {
+    match conc {
+        Atom(a) => out.push(pattern_of_eqterm(&interp_eqwrap(env, a))),
+        Conj(fs) => {
+            for f in fs {
+                conc_atoms(env, f, out)?;
+            }
+        }
+        _ => {
+            return Err(format!(
+                "{} can't be concluded by an axiom, only atoms and /\\ of them can",
+                conc
+            ))
+        }
+    }
+    Ok(())
 }
 /*
 
@@ -871,77 +2659,63 @@ fn interp_searcher(formula : Formula) -> impl Searcher {
 
 }
 
-// Alt pattern implements "Or" search.
-// It should run each of it's searchers and collate the results.
-// Unlike MultiPattern it make no sense as an Applier, since we don't know which case to use.
-struct AltPattern<P>{
-    pats : Vec<P>
-}
-
-// Only succeeds if P fails.
-struct NegPattern<P>{
-    pat : P
-}
 
 */
 
-fn apply_subst(
-    pat: &PatternAst<SymbolLang>,
-    subst: &Subst,
-    egraph: &EGraph<SymbolLang, ()>,
-) -> RecExpr<SymbolLang> {
-    //let mut r = RecExpr::default();
-    //for (i, pat_node) in pat.iter().enumerate() {
+// Copies `expr` onto the end of `r`, returning the id of its root.
+fn append_expr(r: &mut LogExpr, expr: &LogExpr) -> Id {
+    let mut ids: Vec<Id> = vec![];
+    for node in expr.as_ref() {
+        let node = node.clone().map_children(|c| ids[usize::from(c)]);
+        ids.push(r.add(node));
+    }
+    *ids.last().unwrap()
+}
+
+fn apply_subst(pat: &PatternAst<EggLog>, subst: &Subst, egraph: &LogEGraph) -> LogExpr {
     fn worker(
         i: Id,
-        pat: &PatternAst<SymbolLang>,
+        pat: &PatternAst<EggLog>,
         subst: &Subst,
-        egraph: &EGraph<SymbolLang, ()>,
-    ) -> String {
+        egraph: &LogEGraph,
+        r: &mut LogExpr,
+    ) -> Id {
         match &pat[i] {
-            ENodeOrVar::Var(w) => {
-                //let expr = simplify(egraph,subst[*w] );
-                // r.add(egraph.extract(subst[*w]))
-                simplify(egraph, *subst.get(*w).unwrap()).to_string()
-            }
+            ENodeOrVar::Var(w) => append_expr(r, &simplify(egraph, *subst.get(*w).unwrap())),
             ENodeOrVar::ENode(e) => {
-                let args: String = e
-                    .children
-                    .iter()
-                    .map(|i| worker(*i, pat, subst, egraph))
-                    .collect();
-                format!("({} {})", e.op.to_string(), args)
-                //let n = e.clone().map_children(|child| ids[usize::from(child)]);
-                //egraph.add(n)
+                let n = e
+                    .clone()
+                    .map_children(|child| worker(child, pat, subst, egraph, r));
+                r.add(n)
             }
         }
     }
-    worker((pat.as_ref().len() - 1).into(), pat, subst, egraph)
-        .parse()
-        .unwrap()
+    let mut r = RecExpr::default();
+    worker((pat.as_ref().len() - 1).into(), pat, subst, egraph, &mut r);
+    r
 }
 
 use core::time::Duration;
+use instant::Instant;
 // Refactor this to return not string.
-fn run_file(file: Vec<Entry>, opts: &Opts) -> String {
-    //let mut env = Env::default();
-    let mut prog = Program::default();
-
-    for entry in file {
-        //process_entry(&mut env, entry)
-        process_entry_prog(&mut prog, entry)
-    }
-    let runner = Runner::default()
-        .with_iter_limit(30)
-        .with_node_limit(10_000)
-        .with_time_limit(Duration::from_secs(5))
-        .with_explanations_enabled();
-    let (mut runner, query_results) = run_program(&prog, runner);
-    // Two useful things to turn on. Command line arguments?
-    //runner.print_report();
-    // runner.egraph.dot().to_png("target/foo.png").unwrap();
+fn run_file(prog: Program, opts: &Opts) -> Result<String, String> {
+    let runner = Runner::default().with_explanations_enabled();
     let mut buf = String::new();
-    for (q, res) in prog.queries.iter().zip(query_results) {
+    let bans = Bans::default();
+    let mut answered = vec![];
+    let _runner = run_program(&prog, opts, &bans, runner, |runner, q, res| {
+        // exists! also needs every answer to bind the same witness
+        let unique = prog.unique.get(&answered.len()).map_or(true, |vs| {
+            vs.iter().all(|v| {
+                let v: Var = format!("?{}", v).parse().unwrap();
+                let ids: HashSet<Option<Id>> = res
+                    .iter()
+                    .map(|s| s.get(v).map(|id| runner.egraph.find(*id)))
+                    .collect();
+                ids.len() == 1 && !ids.contains(&None)
+            })
+        });
+        answered.push(!res.is_empty() && unique);
         writeln!(buf, "-? {}", q);
         //let matches = q.search(&runner.egraph);
         if res.len() == 0 {
@@ -950,8 +2724,10 @@ fn run_file(file: Vec<Entry>, opts: &Opts) -> String {
             for subst in res {
                 print_subst(&mut buf, &runner.egraph, &subst);
                 if opts.proof {
-                    for ab in &q.patterns {
-                        if let Eq(a, b) = ab {
+                    // A chain a = b = c gets a proof for each step
+                    for ab in q.equations() {
+                        for step in ab.terms().windows(2) {
+                            let (a, b) = (step[0], step[1]);
                             /*
                             let ast = a.ast.as_ref();
                             let mut id_buf = vec![0.into(); ast.len()];
@@ -977,8 +2753,35 @@ fn run_file(file: Vec<Entry>, opts: &Opts) -> String {
                 }
             }
         }
+        // A goal that was taken apart is decided once the last of its queries is answered
+        for proof in &prog.goals {
+            if proof.last() + 1 == answered.len() {
+                proof.show(&mut buf, &prog.queries, &answered, 0).unwrap();
+                let verdict = if proof.proven(&answered) {
+                    "proved"
+                } else {
+                    "unknown"
+                };
+                writeln!(buf, "{}.", verdict);
+            }
+        }
+    });
+    // Two useful things to turn on. Command line arguments?
+    //runner.print_report();
+    // runner.egraph.dot().to_png("target/foo.png").unwrap();
+    if opts.report && bans.borrow().is_empty() {
+        writeln!(buf, "No rules were banned.");
+    } else if opts.report {
+        writeln!(buf, "Banned rules:");
+        for (rule, (times, iters)) in bans.borrow().iter() {
+            writeln!(
+                buf,
+                "{}: banned {} times for {} iterations",
+                rule, times, iters
+            );
+        }
     }
-    buf
+    Ok(buf)
 }
 
 use clap::{AppSettings, Clap};
@@ -999,6 +2802,48 @@ pub struct Opts {
     /// Output graphical representation TODO
     #[clap(short, long)]
     pub graph: Option<String>,
+    /// Add a directory to search for included files. Also read from EGGLOG_PATH
+    #[clap(short = 'I', long, number_of_values = 1)]
+    pub include: Vec<String>,
+    /// Maximum number of enodes. Overrides :- node_limit(N).
+    #[clap(long)]
+    pub node_limit: Option<usize>,
+    /// Maximum number of iterations. Overrides :- iter_limit(N).
+    #[clap(long)]
+    pub iter_limit: Option<usize>,
+    /// Time limit in seconds. Overrides :- time_limit(Secs).
+    #[clap(long, parse(try_from_str = parse_secs))]
+    pub time_limit: Option<f64>,
+    /// Rewrite scheduler, simple or backoff
+    #[clap(long, default_value = "backoff")]
+    pub scheduler: SchedulerKind,
+    /// Matches a rule may find before backoff bans it. Rules can override with :- match_limit(name, N).
+    #[clap(long, default_value = "1000")]
+    pub match_limit: usize,
+    /// Iterations a rule is first banned for. Rules can override with :- ban_length(name, N).
+    #[clap(long, default_value = "5")]
+    pub ban_length: usize,
+    /// Print which rules the backoff scheduler banned, and for how long
+    #[clap(long)]
+    pub report: bool,
+    /// Keep running to the limits after every query is proven
+    #[clap(long)]
+    pub all_answers: bool,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clap)]
+pub enum Command {
+    /// Rewrite files in canonical form, keeping comments
+    Fmt(FmtOpts),
+}
+
+#[derive(Clap)]
+pub struct FmtOpts {
+    /// Files to format in place
+    #[clap(required = true)]
+    pub files: Vec<String>,
 }
 
 impl Default for Opts {
@@ -1008,13 +2853,156 @@ impl Default for Opts {
             verbose: false,
             proof: false,
             graph: None,
+            include: vec![],
+            node_limit: None,
+            iter_limit: None,
+            time_limit: None,
+            scheduler: SchedulerKind::Backoff,
+            match_limit: 1000,
+            ban_length: 5,
+            report: false,
+            all_answers: false,
+            command: None,
         }
     }
 }
 
 pub fn run(s: String, opts: &Opts) -> Result<String, String> {
-    let f = parse_file(s)?;
-    Ok(run_file(f, opts))
+    reset_ops();
+    let mut prog = Program {
+        includes: Includes::new(opts),
+        ..Program::default()
+    };
+    load_source(&mut prog, &s, opts.filename.as_deref())?;
+    run_file(prog, opts)
+}
+
+/// Rewrites a file in canonical form. The file is left alone if it does not parse.
+pub fn fmt_file(filename: &str, opts: &Opts) -> Result<(), String> {
+    reset_ops();
+    let mut includes = Includes::new(&Opts {
+        filename: Some(filename.to_string()),
+        include: opts.include.clone(),
+        ..Opts::default()
+    });
+    let contents = fs::read_to_string(filename)
+        .map_err(|e| format!("could not read file {} : {}", filename, e))?;
+    let formatted = format_source(&contents, |entry| {
+        if let Directive(types::Directive::Include(f)) = entry {
+            includes.declare_ops(f);
+        }
+    })
+    .map_err(|e| e.in_file(filename).to_string())?;
+    if formatted != contents {
+        fs::write(filename, formatted)
+            .map_err(|e| format!("could not write file {} : {}", filename, e))?;
+    }
+    Ok(())
+}
+
+/// Runs an SMT-LIB2 script, answering each `(check-sat)` with `unsat` when one of the negated
+/// goals asserted before it is proven, and `unknown` otherwise.
+pub fn run_smt2(s: String, opts: &Opts) -> Result<String, String> {
+    reset_ops();
+    let script = parse_smt2(&s)?;
+    let mut buf = String::new();
+    let mut prog = Program::default();
+    let mut entries = script.entries.into_iter();
+    let mut done = 0;
+    for n in script.check_sats {
+        for entry in entries.by_ref().take(n - done) {
+            process_entry_prog(&mut prog, entry)?;
+        }
+        done = n;
+        let proven = run_program2(&prog, opts).iter().any(|res| !res.is_empty());
+        writeln!(buf, "{}", if proven { "unsat" } else { "unknown" }).unwrap();
+    }
+    Ok(buf)
+}
+
+// Loads a TPTP file into prog, keeping `conjecture` for each goal it adds.
+// Errors come with the SZS status they should be reported as.
+fn load_tptp(
+    prog: &mut Program,
+    contents: &str,
+    only: Option<&[String]>,
+    conjecture: &mut Vec<bool>,
+) -> Result<(), (&'static str, String)> {
+    for input in parse_tptp(contents).map_err(|e| ("SyntaxError", e))? {
+        match input {
+            TptpInput::Include(filename, names) => {
+                let path = prog
+                    .includes
+                    .resolve(&filename)
+                    .and_then(|p| fs::canonicalize(p).ok())
+                    .ok_or_else(|| ("InputError", format!("file {} not found", filename)))?;
+                if prog.includes.stack.contains(&path) {
+                    return Err(("InputError", format!("include cycle at {}", filename)));
+                }
+                let contents = fs::read_to_string(&path).map_err(|e| {
+                    (
+                        "InputError",
+                        format!("could not read file {} : {}", filename, e),
+                    )
+                })?;
+                prog.includes.stack.push(path);
+                let res = load_tptp(prog, &contents, names.as_deref(), conjecture);
+                prog.includes.stack.pop();
+                res.map_err(|(status, e)| (status, format!("{}: {}", filename, e)))?;
+            }
+            TptpInput::Formula(f) if only.map_or(true, |names| names.contains(&f.name)) => {
+                for entry in f.entries().map_err(|e| ("Inappropriate", e))? {
+                    if let Query(_) | Goal(_) = entry {
+                        conjecture.push(f.is_conjecture());
+                    }
+                    process_entry_prog(prog, entry).map_err(|e| ("Error", e))?;
+                }
+            }
+            TptpInput::Formula(_) => (),
+        }
+    }
+    Ok(())
+}
+
+/// Runs a TPTP problem and reports an SZS status. Conjectures are Theorems when all of them
+/// are proven, clauses from negated conjectures are Unsatisfiable when any of them is refuted.
+/// Includes are also looked for under the directory in the TPTP environment variable.
+pub fn run_tptp(s: String, opts: &Opts) -> String {
+    reset_ops();
+    let mut prog = Program {
+        includes: Includes::new(opts),
+        ..Program::default()
+    };
+    if let Some(root) = std::env::var_os("TPTP") {
+        prog.includes.search_path.insert(0, PathBuf::from(root));
+    }
+    let problem = opts
+        .filename
+        .as_ref()
+        .and_then(|f| Path::new(f).file_stem())
+        .map(|f| format!(" for {}", f.to_string_lossy()))
+        .unwrap_or_default();
+    let mut conjecture = vec![];
+    if let Err((status, e)) = load_tptp(&mut prog, &s, None, &mut conjecture) {
+        return format!("% SZS status {}{}\n% {}\n", status, problem, e);
+    }
+    let proven: Vec<(bool, bool)> = run_program2(&prog, opts)
+        .iter()
+        .zip(conjecture)
+        .map(|(res, conj)| (conj, !res.is_empty()))
+        .collect();
+    let conjectures: Vec<bool> = proven.iter().filter(|g| g.0).map(|g| g.1).collect();
+    let refuted = proven.iter().any(|&(conj, p)| !conj && p);
+    let status = if !conjectures.is_empty() && conjectures.iter().all(|p| *p) {
+        "Theorem"
+    } else if refuted && !conjectures.is_empty() {
+        "ContradictoryAxioms"
+    } else if refuted {
+        "Unsatisfiable"
+    } else {
+        "GaveUp"
+    };
+    format!("% SZS status {}{}\n", status, problem)
 }
 
 use wasm_bindgen::prelude::*;
@@ -1028,10 +3016,23 @@ pub fn run_wasm_simple(s: String) -> String {
 }
 
 #[wasm_bindgen]
-pub fn run_wasm(s: String, proof: bool, graph: bool) -> String {
+pub fn run_wasm(
+    s: String,
+    proof: bool,
+    graph: bool,
+    node_limit: Option<usize>,
+    iter_limit: Option<usize>,
+    time_limit: Option<f64>,
+) -> String {
+    if let Some(Err(e)) = time_limit.map(check_secs) {
+        return e;
+    }
     let opts = Opts::default();
     let opts = Opts {
         proof,
+        node_limit,
+        iter_limit,
+        time_limit,
         //graph : if graph {Some "graphout.viz"} else None,
         ..opts
     };
@@ -66,11 +66,31 @@ fn main() {
     // TODO: better command line grabber
     // Interactve mode?
     let opts: Opts = Opts::parse();
+    if let Some(Command::Fmt(fmt)) = &opts.command {
+        for filename in &fmt.files {
+            if let Err(err) = fmt_file(filename, &opts) {
+                println!("Error : \n {}", err);
+            }
+        }
+        return;
+    }
     let filename = &opts.filename; // td::env::args().nth(1).expect("no file path given");
     match filename {
         Some(filename) => {
             let contents =
                 fs::read_to_string(filename).expect("Something went wrong reading the file");
+            if filename.ends_with(".smt2") {
+                // Answer in SMT-LIB style
+                match run_smt2(contents, &opts) {
+                    Ok(res) => print!("{}", res),
+                    Err(err) => println!("(error {:?})", err),
+                }
+                return;
+            }
+            if filename.ends_with(".p") || filename.ends_with(".tptp") {
+                print!("{}", run_tptp(contents, &opts));
+                return;
+            }
             match run(contents, &opts) {
                 Ok(res) => println!("Results : \n{}", res),
                 Err(err) => println!("Error : \n {}", err),
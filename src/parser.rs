@@ -1,28 +1,50 @@
 use crate::*;
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_until},
-    character::complete::{alphanumeric0, alphanumeric1, char, multispace0, multispace1, satisfy},
-    combinator::{map, opt, value},
-    error::ParseError,
-    multi::{many0, separated_list0, separated_list1},
-    sequence::{delimited, pair, preceded, terminated, tuple},
+    bytes::complete::{tag, take_until, take_while},
+    character::complete::{char, digit1, multispace1, satisfy},
+    combinator::{cut, map, map_res, not, opt, recognize, value, verify},
+    error::{make_error, ErrorKind, ParseError},
+    multi::{many0, many1, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, terminated},
     IResult,
 };
+use std::cell::RefCell;
+use std::fmt;
 
 fn clause(input: &str) -> IResult<&str, Entry> {
     let (input, head) = separated_list1(ws(char(',')), eqterm)(input)?;
-    let (input, body) = preceded(ws(tag(":-")), separated_list1(ws(char(',')), eqterm))(input)?;
+    let (input, body) = preceded(ws(tag(":-")), conds)(input)?;
     Ok((input, Clause(head, body)))
 }
 
+// Conditions joined by `,` and `;`, where `,` binds tighter as in Prolog.
+fn conds(input: &str) -> IResult<&str, Vec<Cond>> {
+    let conj = map(separated_list1(ws(char(',')), cond), |cs| cs.concat());
+    map(separated_list1(ws(char(';')), conj), |mut branches| {
+        if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            vec![Cond::Or(branches)]
+        }
+    })(input)
+}
+
+// `\+ p(X)` or `not p(X)`, an equation, or conditions in parentheses.
+// A `not` has to be followed by a space, so not(x) is still a term.
+fn cond(input: &str) -> IResult<&str, Vec<Cond>> {
+    let negation = alt((tag("\\+"), terminated(tag("not"), multispace1)));
+    alt((
+        map(preceded(ws(negation), eqterm), |e| vec![Cond::Not(e)]),
+        map(eqterm, |e| vec![Cond::Pos(e)]),
+        delimited(ws(char('(')), conds, ws(char(')'))),
+    ))(input)
+}
+
 fn rewrite(input: &str) -> IResult<&str, Entry> {
     let (input, a) = terminated(term, ws(tag("<-")))(input)?;
     let (input, b) = term(input)?;
-    let (input, body) = opt(preceded(
-        ws(tag(",")),
-        separated_list1(ws(char(',')), eqterm),
-    ))(input)?;
+    let (input, body) = opt(preceded(ws(tag(",")), conds))(input)?;
     let body = match body {
         None => vec![],
         Some(v) => v,
@@ -37,10 +59,13 @@ fn birewrite(input: &str) -> IResult<&str, Entry> {
 }
 
 fn query(input: &str) -> IResult<&str, Entry> {
-    map(
-        preceded(ws(tag("?-")), separated_list1(ws(char(',')), eqterm)),
-        |eqterms| Query(eqterms),
-    )(input)
+    alt((
+        map(preceded(ws(tag("?-")), conds), Query),
+        map(
+            preceded(ws(tag("?+")), separated_list1(ws(char(',')), eqterm)),
+            InsertQuery,
+        ),
+    ))(input)
 }
 /*
 fn forall(input: &str) -> IResult<&str, Formula> {
@@ -69,124 +94,853 @@ fn formula(input: &str) -> IResult<&str, Formula> {
 fn include(input: &str) -> IResult<&str, Directive> {
     map(
         delimited(ws(tag("include(")), take_until(")"), ws(tag(")"))),
-        |filename: &str| Directive::Include(filename.to_string()),
+        |filename: &str| {
+            // include("foo.pl") and include('foo.pl') are also accepted
+            let filename = filename.trim().trim_matches(|c| c == '"' || c == '\'');
+            Directive::Include(filename.to_string())
+        },
+    )(input)
+}
+
+fn op_type(input: &str) -> IResult<&str, OpType> {
+    use OpType::*;
+    alt((
+        value(Xfx, tag("xfx")),
+        value(Xfy, tag("xfy")),
+        value(Yfx, tag("yfx")),
+        value(Fy, tag("fy")),
+        value(Fx, tag("fx")),
+        value(Xf, tag("xf")),
+        value(Yf, tag("yf")),
+    ))(input)
+}
+
+// :- op(500, yfx, +).
+fn op_decl(input: &str) -> IResult<&str, Directive> {
+    let (input, _) = preceded(ws(tag("op")), ws(char('(')))(input)?;
+    let (input, priority) = verify(map_res(digit1, |d: &str| d.parse::<usize>()), |p| {
+        *p <= 1200
+    })(input)?;
+    let (input, typ) = preceded(ws(char(',')), op_type)(input)?;
+    // Quoting is allowed, but the name must still be readable as an operator.
+    let name = alt((
+        map(op_name, String::from),
+        verify(quoted_atom, |s: &str| matches!(op_name(s), Ok(("", _)))),
+    ));
+    let (input, name) = delimited(ws(char(',')), name, ws(char(')')))(input)?;
+    Ok((input, Directive::Op(priority, typ, name)))
+}
+
+// :- node_limit(10000). :- iter_limit(30). :- time_limit(2.5).
+fn limit(input: &str) -> IResult<&str, Directive> {
+    let count = || map_res(digit1, |d: &str| d.parse::<usize>());
+    let secs = map_res(
+        recognize(pair(digit1, opt(pair(char('.'), digit1)))),
+        |d: &str| {
+            d.parse::<f64>()
+                .map_err(|e| e.to_string())
+                .and_then(check_secs)
+        },
+    );
+    let arg = |name| pair(ws(tag(name)), ws(char('(')));
+    terminated(
+        alt((
+            map(preceded(arg("node_limit"), count()), Directive::NodeLimit),
+            map(preceded(arg("iter_limit"), count()), Directive::IterLimit),
+            // cut, so the error points at a number too big rather than at time_limit
+            map(preceded(arg("time_limit"), cut(secs)), Directive::TimeLimit),
+        )),
+        ws(char(')')),
+    )(input)
+}
+
+// :- run. :- run(5). :- saturate.
+fn run(input: &str) -> IResult<&str, Directive> {
+    let iters = delimited(
+        ws(char('(')),
+        map_res(digit1, |d: &str| d.parse::<usize>()),
+        ws(char(')')),
+    );
+    alt((
+        map(preceded(ws(tag("run")), opt(iters)), Directive::Run),
+        map(ws(tag("saturate")), |_| Directive::Saturate),
+    ))(input)
+}
+
+// :- push. :- pop. :- clear.
+fn scope(input: &str) -> IResult<&str, Directive> {
+    alt((
+        map(ws(tag("push")), |_| Directive::Push),
+        map(ws(tag("pop")), |_| Directive::Pop),
+        map(ws(tag("clear")), |_| Directive::Clear),
+    ))(input)
+}
+
+fn schedule(input: &str) -> IResult<&str, Schedule> {
+    let args = |name| pair(ws(tag(name)), ws(char('(')));
+    let count = map_res(digit1, |d: &str| d.parse::<usize>());
+    alt((
+        map(
+            delimited(
+                args("seq"),
+                separated_list1(ws(char(',')), schedule),
+                ws(char(')')),
+            ),
+            Schedule::Seq,
+        ),
+        map(
+            delimited(
+                args("repeat"),
+                pair(ws(count), preceded(ws(char(',')), schedule)),
+                ws(char(')')),
+            ),
+            |(n, s)| Schedule::Repeat(n, Box::new(s)),
+        ),
+        map(delimited(args("saturate"), schedule, ws(char(')'))), |s| {
+            Schedule::Saturate(Box::new(s))
+        }),
+        map(ws(atom_name), Schedule::Run),
+    ))(input)
+}
+
+// :- ruleset(rewrites). :- disable(comm). :- schedule(repeat(5, rewrites)).
+// :- match_limit(comm, 100). :- ban_length(comm, 2).
+fn rules(input: &str) -> IResult<&str, Directive> {
+    let arg = |name| pair(ws(tag(name)), ws(char('(')));
+    let count = || {
+        preceded(
+            ws(char(',')),
+            map_res(ws(digit1), |d: &str| d.parse::<usize>()),
+        )
+    };
+    terminated(
+        alt((
+            map(preceded(arg("ruleset"), ws(atom_name)), Directive::Ruleset),
+            map(preceded(arg("disable"), ws(atom_name)), Directive::Disable),
+            map(preceded(arg("schedule"), schedule), Directive::Schedule),
+            map(
+                preceded(arg("match_limit"), pair(ws(atom_name), count())),
+                |(name, n)| Directive::MatchLimit(name, n),
+            ),
+            map(
+                preceded(arg("ban_length"), pair(ws(atom_name), count())),
+                |(name, n)| Directive::BanLength(name, n),
+            ),
+        )),
+        ws(char(')')),
     )(input)
 }
 
 fn directive(input: &str) -> IResult<&str, Entry> {
-    map(preceded(ws(tag(":-")), include), |d| Directive(d))(input)
+    map(
+        preceded(
+            ws(tag(":-")),
+            alt((include, op_decl, limit, rules, run, scope)),
+        ),
+        |d| Directive(d),
+    )(input)
 }
 
 fn fact(input: &str) -> IResult<&str, Entry> {
     map(eqgroundterm, |a| Fact(a))(input)
 }
 
-fn entry(input: &str) -> IResult<&str, Entry> {
+// name :: rule
+fn named(input: &str) -> IResult<&str, Entry> {
+    let (input, name) = terminated(ws(atom_name), ws(tag("::")))(input)?;
+    let (input, rule) = alt((birewrite, rewrite, clause))(input)?;
+    Ok((input, Named(name, Box::new(rule))))
+}
+
+// An entry up to and including its `.`
+fn entry_body(input: &str) -> IResult<&str, Entry> {
     // I should factor this more.
-    ws(terminated(
+    terminated(
         alt((
-            query, directive, axiom, goal, birewrite, rewrite, clause, fact,
+            query, directive, axiom, goal, named, birewrite, rewrite, clause, fact,
         )),
         char('.'),
-    ))(input)
+    )(input)
 }
 
-pub fn pinline_comment<'a>(i: &'a str) -> IResult<&'a str, ()> {
+fn entry(input: &str) -> IResult<&str, Entry> {
+    ws(entry_body)(input)
+}
+
+// Block comments nest, so a block that already has comments in it can be commented out.
+pub fn pinline_comment<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, (), E> {
+    let (mut rest, _) = tag("/*")(i)?;
+    let mut depth = 1;
+    while depth > 0 {
+        if rest.starts_with("*/") {
+            depth -= 1;
+            rest = &rest[2..];
+        } else if rest.starts_with("/*") {
+            depth += 1;
+            rest = &rest[2..];
+        } else if let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+        } else {
+            return Err(nom::Err::Error(E::from_error_kind(i, ErrorKind::TakeUntil)));
+        }
+    }
+    Ok((rest, ()))
+}
+
+// % and // comment out the rest of the line.
+fn line_comment<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, (), E> {
     value(
-        (), // Output is thrown away.
-        tuple((tag("/*"), take_until("*/"), tag("*/"))),
+        (),
+        pair(alt((tag("%"), tag("//"))), take_while(|c| c != '\n')),
     )(i)
 }
 
-fn file(input: &str) -> IResult<&str, Vec<Entry>> {
-    let (input, _) = many0(ws(pinline_comment))(input)?;
-    many0(terminated(entry, many0(ws(pinline_comment))))(input)
-    //many0(alt((entry, map(pinline_comment, )))(input)
+fn comment<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, (), E> {
+    alt((line_comment, pinline_comment))(i)
 }
 
-pub fn parse_file(input: String) -> Result<Vec<Entry>, String> {
-    // input.retain(|c| !c.is_whitespace());
-    match file(&input) {
-        Ok((rem, f)) => {
-            if rem.is_empty() {
-                Ok(f)
-            } else {
-                Err(format!("Remainder: {}", rem))
+/// Whitespace and comments.
+pub(crate) fn sp<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, (), E> {
+    value((), many0(alt((value((), multispace1), comment))))(i)
+}
+
+/// At least some whitespace or a comment. Separates juxtaposed arguments as in `f x y`.
+fn sp1<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, (), E> {
+    value((), many1(alt((value((), multispace1), comment))))(i)
+}
+
+/// A parse error located in the source text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub file: Option<String>,
+    pub line: usize,   // 1-based
+    pub column: usize, // 1-based, in characters
+    pub source_line: String,
+    pub expected: String,
+}
+
+impl SyntaxError {
+    fn new(input: &str, rest: &str, expected: String) -> Self {
+        let offset = input.len() - rest.len();
+        let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[offset..]
+            .find('\n')
+            .map_or(input.len(), |i| offset + i);
+        SyntaxError {
+            file: None,
+            line: input[..offset].matches('\n').count() + 1,
+            column: input[line_start..offset].chars().count() + 1,
+            source_line: input[line_start..line_end].trim_end().to_string(),
+            expected,
+        }
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+        writeln!(f, "{}:{}: {}", self.line, self.column, self.expected)?;
+        writeln!(f, "    {}", self.source_line)?;
+        // Keep tabs so the caret lines up with the source line.
+        let pad: String = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(f, "    {}^", pad)
+    }
+}
+
+/// Every syntax error found in a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxErrors(pub Vec<SyntaxError>);
+
+impl SyntaxErrors {
+    pub fn in_file(mut self, filename: &str) -> Self {
+        for e in &mut self.0 {
+            e.file = Some(filename.to_string());
+        }
+        self
+    }
+}
+
+impl fmt::Display for SyntaxErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for e in &self.0 {
+            writeln!(f, "{}", e)?;
+        }
+        Ok(())
+    }
+}
+
+fn skip_comments(input: &str) -> &str {
+    match sp::<()>(input) {
+        Ok((rest, _)) => rest,
+        Err(_) => input,
+    }
+}
+
+fn describe_next(rest: &str) -> String {
+    match rest.trim_start().chars().next() {
+        // The argument list parsers are optional, so a broken one shows up as a stray paren.
+        Some('(') => "`(` (malformed or unbalanced argument list?)".to_string(),
+        Some(c) => format!("`{}`", c),
+        None => "end of file".to_string(),
+    }
+}
+
+// Figure out why `entry` failed on `rest` by trying each kind of entry separately.
+// Whichever got furthest into the input explains the error best.
+fn diagnose(input: &str, rest: &str) -> SyntaxError {
+    let kinds: [(&str, fn(&str) -> IResult<&str, Entry>); 8] = [
+        ("query", query),
+        ("directive", directive),
+        ("axiom", axiom),
+        ("goal", goal),
+        ("bidirectional rewrite", birewrite),
+        ("rewrite", rewrite),
+        ("clause", clause),
+        ("fact", fact),
+    ];
+    // On ties, an entry that parsed but lacks its `.` is the better explanation.
+    let mut best: Option<(&str, bool, String)> = None;
+    for (kind, p) in kinds.iter() {
+        let (pos, failed, expected) = match p(rest) {
+            Ok((r, _)) => {
+                let r = r.trim_start();
+                let expected = format!("expected `.` after {}, found {}", kind, describe_next(r));
+                (r, false, expected)
+            }
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                let r = e.input.trim_start();
+                if r.len() == rest.len() {
+                    continue; // Didn't get anywhere.
+                }
+                (
+                    r,
+                    true,
+                    format!("unexpected {} in {}", describe_next(r), kind),
+                )
             }
+            Err(nom::Err::Incomplete(_)) => continue,
+        };
+        if best
+            .as_ref()
+            .map_or(true, |(r, f, _)| (pos.len(), failed) < (r.len(), *f))
+        {
+            best = Some((pos, failed, expected));
+        }
+    }
+    match best {
+        Some((pos, _, expected)) => SyntaxError::new(input, pos, expected),
+        None => SyntaxError::new(
+            input,
+            rest,
+            "expected a fact, clause, rewrite, query, directive, axiom or goal".to_string(),
+        ),
+    }
+}
+
+// Skip past the end of a broken entry so parsing can resume at the next one. That is a `.`
+// followed by whitespace, a comment or the end of the file, and not one in a string,
+// quoted atom, comment or number like 1.5.
+fn skip_entry(mut rest: &str) -> &str {
+    while let Some(c) = rest.chars().next() {
+        if let Ok((r, _)) = comment::<()>(rest) {
+            rest = r;
+        } else if let Ok((r, _)) = alt((string_lit, quoted_atom))(rest) {
+            rest = r;
+        } else {
+            rest = &rest[c.len_utf8()..];
+            let end = rest.chars().next().map_or(true, char::is_whitespace)
+                || comment::<()>(rest).is_ok();
+            if c == '.' && end {
+                return rest;
+            }
+        }
+    }
+    rest
+}
+
+/// Parses one entry at a time, so that directives such as `:- op(...)` take effect
+/// before the rest of the file is parsed.
+pub struct Entries<'a> {
+    input: &'a str,
+    rest: &'a str,
+}
+
+/// The entries of a file, starting from the default operators.
+pub fn entries(input: &str) -> Entries {
+    reset_ops();
+    included_entries(input)
+}
+
+// An included file keeps the operators declared before it
+pub(crate) fn included_entries(input: &str) -> Entries {
+    Entries {
+        input,
+        rest: skip_comments(input),
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<Entry, SyntaxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let res = match entry(self.rest) {
+            Ok((r, e)) => {
+                if let Directive(Directive::Op(priority, typ, name)) = &e {
+                    declare_op(*priority, *typ, name);
+                }
+                self.rest = r;
+                Ok(e)
+            }
+            // skip_comments stops at the start of a comment it couldn't finish
+            Err(_) if self.rest.starts_with("/*") => {
+                let err =
+                    SyntaxError::new(self.input, self.rest, "unterminated comment".to_string());
+                self.rest = "";
+                Err(err)
+            }
+            Err(_) => {
+                let err = diagnose(self.input, self.rest);
+                self.rest = skip_entry(self.rest);
+                Err(err)
+            }
+        };
+        self.rest = skip_comments(self.rest);
+        Some(res)
+    }
+}
+
+// The comments in `text`, skipping over quoted atoms and strings.
+fn comments_in(text: &str) -> Vec<&str> {
+    let mut found = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        rest = if let Ok((r, _)) = comment::<()>(rest) {
+            found.push(&rest[..rest.len() - r.len()]);
+            r
+        } else if let Ok((r, _)) = alt((quoted_atom, string_lit))(rest) {
+            r
+        } else {
+            &rest[c.len_utf8()..]
+        };
+    }
+    found
+}
+
+/// Reprints a file in canonical form, one entry per line. Comments are kept: those between
+/// entries stay where they are, those inside an entry are moved to the lines above it.
+/// Runs of blank lines become a single blank line.
+/// `on_entry` sees each entry as it is parsed, in time to declare operators from included files.
+pub fn format_source(
+    input: &str,
+    mut on_entry: impl FnMut(&Entry),
+) -> Result<String, SyntaxErrors> {
+    reset_ops();
+    let mut out = String::new();
+    let mut errors = vec![];
+    let mut rest = input;
+    loop {
+        // Whitespace and comments up to the next entry
+        let (r, _) = sp::<()>(rest).unwrap_or((rest, ()));
+        let mut gap = &rest[..rest.len() - r.len()];
+        rest = r;
+        while !gap.is_empty() {
+            let blank = gap.starts_with(char::is_whitespace);
+            let ws_len = gap.len() - gap.trim_start().len();
+            let newlines = gap[..ws_len].matches('\n').count();
+            gap = &gap[ws_len..];
+            if gap.is_empty() {
+                if newlines >= 2 && !rest.is_empty() && !out.is_empty() {
+                    out.push('\n');
+                }
+                break;
+            }
+            let c = comments_in(gap)[0];
+            gap = &gap[c.len()..];
+            if out.is_empty() {
+            } else if blank && newlines == 0 || !blank {
+                // Trailing comment on the same line as what came before it
+                out.pop();
+                out.push(' ');
+            } else if newlines >= 2 {
+                out.push('\n');
+            }
+            out.push_str(c);
+            out.push('\n');
+        }
+        if rest.is_empty() {
+            break;
+        }
+        match entry_body(rest) {
+            Ok((r, e)) => {
+                if let Directive(Directive::Op(priority, typ, name)) = &e {
+                    declare_op(*priority, *typ, name);
+                }
+                on_entry(&e);
+                for c in comments_in(&rest[..rest.len() - r.len()]) {
+                    out.push_str(c);
+                    out.push('\n');
+                }
+                out.push_str(&e.to_string());
+                out.push('\n');
+                rest = r;
+            }
+            Err(_) if rest.starts_with("/*") => {
+                errors.push(SyntaxError::new(
+                    input,
+                    rest,
+                    "unterminated comment".to_string(),
+                ));
+                break;
+            }
+            Err(_) => {
+                errors.push(diagnose(input, rest));
+                rest = skip_entry(rest);
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(out)
+    } else {
+        Err(SyntaxErrors(errors))
+    }
+}
+
+pub fn parse_file(input: String) -> Result<Vec<Entry>, SyntaxErrors> {
+    let mut entries_ = vec![];
+    let mut errors = vec![];
+    for res in entries(&input) {
+        match res {
+            Ok(e) => entries_.push(e),
+            Err(e) => errors.push(e),
         }
-        Err(e) => Err(e.to_string()),
+    }
+    if errors.is_empty() {
+        Ok(entries_)
+    } else {
+        Err(SyntaxErrors(errors))
     }
 }
-fn upper(input: &str) -> IResult<&str, char> {
-    satisfy(|c| c.is_ascii_uppercase())(input)
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '\''
 }
 
+// Variables start with a capital or `_`. `_` alone is anonymous, every occurrence is a new variable.
 fn var(input: &str) -> IResult<&str, Term> {
-    let (input, (c, s)) = pair(upper, alphanumeric0)(input)?;
-    Ok((input, Var(format!("{}{}", c, s)))) // seems awkward. whatever
+    let (input, v) = recognize(pair(
+        satisfy(|c| c.is_ascii_uppercase() || c == '_'),
+        take_while(is_ident_char),
+    ))(input)?;
+    Ok((input, Var(v.to_string())))
 }
-// TODO: infix operators.
-fn groundterm(input: &str) -> IResult<&str, GroundTerm> {
-    let (input, head) = alphanumeric1(input)?;
+
+// Plain names: f, foo_bar, x', 42
+fn ident(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        satisfy(|c| c.is_ascii_alphanumeric()),
+        take_while(is_ident_char),
+    ))(input)
+}
+
+// Text between `q`s. Backslash escapes the next character, \n is a newline.
+pub(crate) fn quoted(q: char, input: &str) -> IResult<&str, String> {
+    let (rest, _) = char(q)(input)?;
+    let mut text = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, 'n')) => text.push('\n'),
+                Some((_, c)) => text.push(c),
+                None => break,
+            },
+            c if c == q => return Ok((&rest[i + 1..], text)),
+            c => text.push(c),
+        }
+    }
+    Err(nom::Err::Error(make_error(input, ErrorKind::Char)))
+}
+
+fn escape(q: char, text: &str) -> String {
+    let mut quoted = String::new();
+    quoted.push(q);
+    for c in text.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c == q => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push(q);
+    quoted
+}
+
+// 'hello world', '+'
+fn quoted_atom(input: &str) -> IResult<&str, String> {
+    quoted('\'', input)
+}
+
+// "hello world"
+pub fn string_lit(input: &str) -> IResult<&str, String> {
+    quoted('"', input)
+}
+
+pub fn quote_string(s: &str) -> String {
+    escape('"', s)
+}
+
+// Digits not running on into a name like 2x. Too big for an i64 is fine.
+fn number(input: &str) -> IResult<&str, Literal> {
+    map_res(
+        terminated(digit1, not(satisfy(is_ident_char))),
+        |d: &str| d.parse().map(Literal::number),
+    )(input)
+}
+
+fn literal(input: &str) -> IResult<&str, Term> {
+    map(alt((number, map(string_lit, Literal::Str))), Lit)(input)
+}
+
+fn atom_name(input: &str) -> IResult<&str, String> {
+    alt((quoted_atom, map(ident, String::from)))(input)
+}
+
+/// Inverse of `atom_name`. Quotes a name only if it wouldn't read back as itself:
+/// anything that isn't a plain name, or that would read as a variable, number or operator.
+pub fn quote_atom(name: &str) -> String {
+    let plain = matches!(ident(name), Ok(("", _)))
+        && !name.starts_with(|c: char| c.is_ascii_uppercase())
+        && !matches!(number(name), Ok(("", _)))
+        && infix_op(name).is_none()
+        && prefix_op(name).is_none()
+        && postfix_op(name).is_none();
+    if plain {
+        name.to_string()
+    } else {
+        escape('\'', name)
+    }
+}
+
+type Prim = fn(&str) -> IResult<&str, Term>;
+
+// Operators are global parser state, like Prolog's op/3. They are reset to the defaults
+// at the start of each file, other than an included one.
+thread_local! {
+    static OPS: RefCell<Vec<(String, usize, OpType)>> = RefCell::new(default_ops());
+}
+
+fn default_ops() -> Vec<(String, usize, OpType)> {
+    use OpType::*;
+    vec![
+        ("<".to_string(), 700, Xfx),
+        ("+".to_string(), 500, Yfx),
+        ("-".to_string(), 500, Yfx),
+        ("*".to_string(), 400, Yfx),
+        ("/".to_string(), 400, Yfx),
+        ("^".to_string(), 200, Xfy),
+        ("-".to_string(), 200, Fy),
+    ]
+}
+
+pub fn reset_ops() {
+    OPS.with(|ops| *ops.borrow_mut() = default_ops())
+}
+
+/// Declare an operator. As in Prolog, priority 0 removes the declaration.
+pub fn declare_op(priority: usize, typ: OpType, name: &str) {
+    OPS.with(|ops| {
+        let mut ops = ops.borrow_mut();
+        ops.retain(|(n, _, t)| {
+            !(n == name && t.is_infix() == typ.is_infix() && t.is_prefix() == typ.is_prefix())
+        });
+        if priority > 0 {
+            ops.push((name.to_string(), priority, typ));
+        }
+    })
+}
+
+fn lookup_op(name: &str, class: fn(&OpType) -> bool) -> Option<(usize, OpType)> {
+    OPS.with(|ops| {
+        ops.borrow()
+            .iter()
+            .find(|(n, _, t)| n == name && class(t))
+            .map(|(_, p, t)| (*p, *t))
+    })
+}
+
+pub fn infix_op(name: &str) -> Option<(usize, OpType)> {
+    lookup_op(name, OpType::is_infix)
+}
+
+pub fn prefix_op(name: &str) -> Option<(usize, OpType)> {
+    lookup_op(name, OpType::is_prefix)
+}
+
+pub fn postfix_op(name: &str) -> Option<(usize, OpType)> {
+    lookup_op(name, OpType::is_postfix)
+}
+
+pub fn is_symbol_char(c: char) -> bool {
+    "+-*/\\^<>=~:?@#&$".contains(c)
+}
+
+// Symbol sequences that mean something to the entry grammar and so can never be operators.
+const RESERVED: [&str; 12] = [
+    ":-", "<-", "<->", "=", "=>", "/\\", "\\/", "?-", "?+", "|-", "::", "\\+",
+];
+
+// A run of symbol characters, stopping where a comment starts.
+fn symbols(input: &str) -> IResult<&str, &str> {
+    let end = input
+        .char_indices()
+        .find(|&(i, c)| {
+            !is_symbol_char(c) || input[i..].starts_with("/*") || input[i..].starts_with("//")
+        })
+        .map_or(input.len(), |(i, _)| i);
+    if end == 0 {
+        Err(nom::Err::Error(make_error(input, ErrorKind::TakeWhile1)))
+    } else {
+        Ok((&input[end..], &input[..end]))
+    }
+}
+
+fn op_name(input: &str) -> IResult<&str, &str> {
+    alt((verify(symbols, |s: &str| !RESERVED.contains(&s)), ident))(input)
+}
+
+// Precedence climbing over the operator table. `prim` parses the operands.
+// Returns the term along with its priority, which is 0 unless it is an operator application.
+fn op_expr(input: &str, max: usize, prim: Prim) -> IResult<&str, (Term, usize)> {
+    let (mut input, (mut left, mut left_prec)) = op_prefix(input, max, prim)?;
+    while let Ok((rest, name)) = preceded(sp, op_name)(input) {
+        if let Some((p, typ)) = infix_op(name) {
+            let (lmax, rmax) = match typ {
+                OpType::Xfy => (p - 1, p),
+                OpType::Yfx => (p, p - 1),
+                _ => (p - 1, p - 1),
+            };
+            if p <= max && left_prec <= lmax {
+                if let Ok((rest, (right, _))) = op_expr(rest, rmax, prim) {
+                    left = Apply(name.to_string(), vec![left, right]);
+                    left_prec = p;
+                    input = rest;
+                    continue;
+                }
+            }
+        }
+        match postfix_op(name) {
+            Some((p, typ))
+                if p <= max && left_prec <= if typ == OpType::Yf { p } else { p - 1 } =>
+            {
+                left = Apply(name.to_string(), vec![left]);
+                left_prec = p;
+                input = rest;
+            }
+            _ => break,
+        }
+    }
+    Ok((input, (left, left_prec)))
+}
+
+fn op_prefix(input: &str, max: usize, prim: Prim) -> IResult<&str, (Term, usize)> {
+    let (input, _) = sp(input)?;
+    if let Ok((rest, name)) = op_name(input) {
+        match prefix_op(name) {
+            Some((p, typ)) if p <= max => {
+                let amax = if typ == OpType::Fy { p } else { p - 1 };
+                if let Ok((rest, (arg, _))) = op_expr(rest, amax, prim) {
+                    return Ok((rest, (Apply(name.to_string(), vec![arg]), p)));
+                }
+            }
+            _ => (),
+        }
+    }
+    map(prim, |t| (t, 0))(input)
+}
+
+fn ground_apply(input: &str) -> IResult<&str, Term> {
+    let (input, head) = atom_name(input)?;
     let (input, body) = opt(delimited(
         ws(char('(')),
-        separated_list0(ws(char(',')), groundterm), // TODO: whitespace
+        separated_list0(ws(char(',')), groundterm_op), // TODO: whitespace
         ws(char(')')),
     ))(input)?;
     let body = body.unwrap_or(vec![]);
-    Ok((
-        input,
-        GroundTerm {
-            head: head.to_string(),
-            args: body,
-        },
-    ))
+    Ok((input, Apply(head, body)))
+}
+
+fn ground_prim(input: &str) -> IResult<&str, Term> {
+    alt((
+        delimited(ws(char('(')), groundterm_op, ws(char(')'))),
+        literal,
+        ground_apply,
+    ))(input)
+}
+
+// Capitalized names are constants in facts, so ground terms are parsed without var.
+fn groundterm_op(input: &str) -> IResult<&str, Term> {
+    map(|i| op_expr(i, 1200, ground_prim), |(t, _)| t)(input)
+}
+
+fn groundterm(input: &str) -> IResult<&str, GroundTerm> {
+    map(groundterm_op, |t| is_ground(&t).unwrap())(input)
 }
 
 fn eqgroundterm(input: &str) -> IResult<&str, EqWrap<GroundTerm>> {
-    map(
-        pair(groundterm, opt(preceded(ws(char('=')), groundterm))),
-        |(t, ot)| match ot {
-            Some(t2) => Eq(t, t2),
-            None => Bare(t),
-        },
-    )(input)
+    map(separated_list1(ws(char('=')), groundterm), EqWrap::chain)(input)
 }
 
 fn apply(input: &str) -> IResult<&str, Term> {
-    let (input, head) = alphanumeric1(input)?;
+    let (input, head) = atom_name(input)?;
     let (input, body) = opt(delimited(
         ws(char('(')),
         separated_list0(ws(char(',')), term), // TODO: whitespace
         ws(char(')')),
     ))(input)?;
     let body = body.unwrap_or(vec![]);
-    Ok((input, Apply(head.to_string(), body)))
+    Ok((input, Apply(head, body)))
+}
+
+fn prim(input: &str) -> IResult<&str, Term> {
+    alt((
+        delimited(ws(char('(')), term, ws(char(')'))),
+        var,
+        literal,
+        apply,
+    ))(input)
 }
+
 // Behaves incorrectly on capital named terms. Whatever. Don't do that.
 fn term(input: &str) -> IResult<&str, Term> {
-    ws(alt((var, apply)))(input)
+    ws(map(|i| op_expr(i, 1200, prim), |(t, _)| t))(input)
 }
 
 fn eqterm(input: &str) -> IResult<&str, EqWrap<Term>> {
-    map(
-        pair(term, opt(preceded(ws(char('=')), term))),
-        |(t, ot)| match ot {
-            Some(t2) => Eq(t, t2),
-            None => Bare(t),
-        },
-    )(input)
+    map(separated_list1(ws(char('=')), term), EqWrap::chain)(input)
 }
 
 /// A combinator that takes a parser `inner` and produces a parser that also consumes both leading and
-/// trailing whitespace, returning the output of `inner`.
-fn ws<'a, F: 'a, O, E: ParseError<&'a str>>(
+/// trailing whitespace and comments, returning the output of `inner`.
+pub(crate) fn ws<'a, F: 'a, O, E: ParseError<&'a str>>(
     inner: F,
 ) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
 where
     F: FnMut(&'a str) -> IResult<&'a str, O, E>,
 {
-    delimited(multispace0, inner, multispace0)
+    delimited(sp, inner, sp)
 }
 
 /*
@@ -194,12 +948,11 @@ I could redesign it so that variables must be bound.
 
 */
 
-//existsunique
-
 // Hmmm. Should the parens go... somewhere deeper?
 // What about f (f x).
+// Atoms go first so that `(a + b) * c = d` isn't taken for a parenthesized formula.
 fn primform(input: &str) -> IResult<&str, Formula> {
-    alt((delimited(tag("("), form, tag(")")), atom2))(input)
+    alt((atom2, delimited(tag("("), form, tag(")"))))(input)
 }
 
 fn conjform(input: &str) -> IResult<&str, Formula> {
@@ -238,6 +991,11 @@ fn quantifier(input: &str) -> IResult<&str, Formula> {
             Formula::ForAll as fn(Vec<String>, Box<Formula>) -> Formula,
             tag("forall"),
         ),
+        // exists! first, or exists would take its prefix
+        value(
+            Formula::ExistsUnique as fn(Vec<String>, Box<Formula>) -> Formula,
+            tag("exists!"),
+        ),
         // fn(_,_) -> _ also works
         //more cryptic or not? Function pointer casting https://stackoverflow.com/questions/27895946/expected-fn-item-found-a-different-fn-item-when-working-with-function-pointer
         value(
@@ -245,8 +1003,7 @@ fn quantifier(input: &str) -> IResult<&str, Formula> {
             tag("exists"),
         ),
     ))(input)?;
-    let (input, args) =
-        terminated(ws(separated_list1(multispace1, alphanumeric1)), tag(","))(input)?;
+    let (input, args) = terminated(ws(separated_list1(sp1, ident)), tag(","))(input)?;
     let (input, f) = form(input)?;
     Ok((
         input,
@@ -272,27 +1029,31 @@ forall x, => yada.
 fn primterm(input: &str) -> IResult<&str, Term> {
     alt((
         delimited(tag("("), term2, tag(")")),
-        map(alphanumeric1, |s: &str| Apply(s.to_string(), vec![])),
+        literal,
+        map(quoted_atom, |s| Apply(s, vec![])),
+        map(
+            verify(ident, |s: &str| {
+                infix_op(s).is_none() && postfix_op(s).is_none()
+            }),
+            |s: &str| Apply(s.to_string(), vec![]),
+        ),
     ))(input)
 }
 fn apply2(input: &str) -> IResult<&str, Term> {
-    let (input, head) = terminated(alphanumeric1, multispace0)(input)?;
-    let (input, body) = separated_list0(multispace1, primterm)(input)?;
-    Ok((input, Apply(head.to_string(), body)))
+    let (input, head) = terminated(atom_name, sp)(input)?;
+    let (input, body) = separated_list0(sp1, primterm)(input)?;
+    Ok((input, Apply(head, body)))
+}
+fn prim2(input: &str) -> IResult<&str, Term> {
+    alt((delimited(tag("("), term2, tag(")")), literal, apply2))(input)
 }
 // SHould just switch to groundterm
 fn term2(input: &str) -> IResult<&str, Term> {
-    ws(alt((delimited(tag("("), term2, tag(")")), apply2)))(input)
+    ws(map(|i| op_expr(i, 1200, prim2), |(t, _)| t))(input)
 }
 
 fn eqterm2(input: &str) -> IResult<&str, EqWrap<Term>> {
-    map(
-        ws(pair(term2, opt(preceded(ws(char('=')), term2)))),
-        |(t, ot)| match ot {
-            Some(t2) => Eq(t, t2),
-            None => Bare(t),
-        },
-    )(input)
+    map(ws(separated_list1(ws(char('=')), term2)), EqWrap::chain)(input)
 }
 
 fn atom2(input: &str) -> IResult<&str, Formula> {
@@ -300,7 +1061,7 @@ fn atom2(input: &str) -> IResult<&str, Formula> {
 }
 
 fn axiom(input: &str) -> IResult<&str, Entry> {
-    let (input, name) = delimited(ws(tag("Axiom")), alphanumeric1, ws(tag(":")))(input)?;
+    let (input, name) = delimited(ws(tag("Axiom")), ident, ws(tag(":")))(input)?;
     let (input, f) = ws(form)(input)?;
     Ok((input, Axiom(name.to_string(), f)))
 }
@@ -391,6 +1152,13 @@ mod tests {
             )
         );
 
+        let unique = form("exists! x, f x").unwrap().1;
+        assert_eq!(
+            unique,
+            ExistsUnique(vec!["x".to_string()], Box::new(fx.clone()))
+        );
+        assert_eq!(form(&unique.to_string()).unwrap().1, unique);
+
         //assert_eq!(formula2("(f x)").unwrap().1,  Atom( Bare(Apply(f, vec![x]) ) ));
     }
     #[test]
@@ -400,10 +1168,7 @@ mod tests {
         assert_eq!(term("f()").unwrap().1, Apply("f".into(), vec![]));
         assert_eq!(
             entry("f().").unwrap().1,
-            Fact(Bare(GroundTerm {
-                head: f,
-                args: vec![]
-            }))
+            Fact(Bare(GroundTerm::Apply(f, vec![])))
         );
         assert_eq!(entry("x<->x.").unwrap().1, BiRewrite(x.clone(), x));
         /* (clause("f()."));
@@ -415,12 +1180,400 @@ mod tests {
         dbg!(eqterm("f=g")); */
     }
     #[test]
+    fn syntax_errors() {
+        let errs = parse_file("f(x).\ng(x) :- h(x) k(x).\n".to_string()).unwrap_err();
+        assert_eq!(errs.0.len(), 1);
+        let e = &errs.0[0];
+        assert_eq!((e.line, e.column), (2, 14));
+        assert_eq!(e.source_line, "g(x) :- h(x) k(x).");
+        assert_eq!(e.expected, "expected `.` after clause, found `k`");
+        assert_eq!(
+            e.to_string(),
+            "2:14: expected `.` after clause, found `k`\n    g(x) :- h(x) k(x).\n                 ^"
+        );
+
+        // Parsing carries on after an error
+        let errs = parse_file("f(x) g.\nok.\n?- .\nf(x)".to_string())
+            .unwrap_err()
+            .in_file("foo.pl");
+        let lines: Vec<_> = errs.0.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![1, 3, 4]);
+        assert_eq!(errs.0[0].file, Some("foo.pl".to_string()));
+        assert_eq!(
+            errs.0[2].expected,
+            "expected `.` after fact, found end of file"
+        );
+
+        // A `.` in a string, quoted atom, number or comment doesn't end a broken entry
+        let errs = parse_file("f(x) g(\"a.b\", 'c. d', 1.5 /* e. */).\nok.\n?- .\n".to_string())
+            .unwrap_err();
+        let lines: Vec<_> = errs.0.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![1, 3]);
+    }
+    #[test]
+    fn comments() {
+        let file = "% a line comment
+f(x, // trailing
+  /* nested /* comment */ still comment */ y).
+/* f(z). /* inner */ */
+?- f(X /* here */, % and here
+     Y).
+Axiom a: forall x y, g x /* c */ y => h x. // done";
+        let es = parse_file(file.to_string()).unwrap();
+        assert_eq!(es.len(), 3);
+        assert_eq!(es[0], entry("f(x,y).").unwrap().1);
+        assert_eq!(es[1], entry("?- f(X,Y).").unwrap().1);
+        // `//` and `/*` end a symbolic operator name
+        assert_eq!(term("a +/* c */b").unwrap().1, term("a + b").unwrap().1);
+        assert_eq!(term("a +// c\n b").unwrap().1, term("a + b").unwrap().1);
+
+        let errs = parse_file("f(x).\n/* /* */\ng(x).".to_string()).unwrap_err();
+        assert_eq!(errs.0.len(), 1);
+        assert_eq!((errs.0[0].line, errs.0[0].column), (2, 1));
+        assert_eq!(errs.0[0].expected, "unterminated comment");
+    }
+
+    #[test]
+    fn chains() {
+        let c = |s: &str| Apply(s.to_string(), vec![]);
+        assert_eq!(
+            eqterm("a = b = c").unwrap(),
+            ("", MultiEq(vec![c("a"), c("b"), c("c")]))
+        );
+        assert_eq!(eqterm("a = b").unwrap(), ("", Eq(c("a"), c("b"))));
+        let es = parse_file(
+            "f(X) = g(X) = h(X) :- p(X). ?- X = a = b, q. Axiom ax: p x = q x = r x => s."
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            es[1],
+            Query(vec![
+                Cond::Pos(MultiEq(vec![Var("X".to_string()), c("a"), c("b")])),
+                Cond::Pos(Bare(c("q")))
+            ])
+        );
+        // The implication arrow is not another equation
+        assert_eq!(es[2].to_string(), "Axiom ax: p x = q x = r x => s.");
+        assert_eq!(parse_file(es[0].to_string()).unwrap()[0], es[0]);
+    }
+
+    #[test]
+    fn format_round_trip() {
+        let file = "% header
+
+
+:- include(lib.pl).
+:- include(\"my lib.pl\").
+:- op(700,xfx,~~>).
+f(x,'Foo', \"s\\n\", -3, 1/2).   /* trailing */
+x ~~> y.
+(a+b)*c <- c*(a+b), e = f. % rewrite
+a - (b - c) <-> -(x) .
+g(X) = Y :- f(/* inside */ X), Y = h(X,_).
+?- g(X) = Y, f(X).
+
+Axiom ax: forall x y, (p x \\/ q y) /\\ r (f x) => (exists z, x = z).
+|- p (s 0) = true.";
+        let out = format_source(file, |_| ()).unwrap();
+        assert_eq!(
+            out,
+            "% header
+
+:- include(lib.pl).
+:- include(\"my lib.pl\").
+:- op(700, xfx, ~~>).
+f(x, 'Foo', \"s\\n\", -3, 1 / 2). /* trailing */
+x ~~> y.
+(a + b) * c <- c * (a + b), e = f. % rewrite
+a - (b - c) <-> -x.
+/* inside */
+g(X) = Y :- f(X), Y = h(X, _).
+?- g(X) = Y, f(X).
+
+Axiom ax: forall x y, (p x \\/ q y) /\\ r (f x) => (exists z, x = z).
+|- p (s 0) = true.
+"
+        );
+        assert_eq!(format_source(&out, |_| ()).unwrap(), out);
+        assert_eq!(
+            parse_file(out).unwrap(),
+            parse_file(file.to_string()).unwrap()
+        );
+        assert!(format_source("f(x).\ng(.", |_| ()).is_err());
+    }
+    #[test]
+    fn operators() {
+        let app = |f: &str, args: Vec<Term>| Apply(f.to_string(), args);
+        let c = |x: &str| app(x, vec![]);
+        let v = |x: &str| Var(x.to_string());
+        assert_eq!(
+            term("a + b * c").unwrap().1,
+            app("+", vec![c("a"), app("*", vec![c("b"), c("c")])])
+        );
+        assert_eq!(
+            term("a - b - c").unwrap().1,
+            app("-", vec![app("-", vec![c("a"), c("b")]), c("c")])
+        );
+        assert_eq!(
+            term("a ^ b ^ c").unwrap().1,
+            app("^", vec![c("a"), app("^", vec![c("b"), c("c")])])
+        );
+        assert_eq!(
+            term("(X + Y) * -f(Z)").unwrap().1,
+            app(
+                "*",
+                vec![
+                    app("+", vec![v("X"), v("Y")]),
+                    app("-", vec![app("f", vec![v("Z")])])
+                ]
+            )
+        );
+        assert_eq!(
+            entry("A + B <- B + A.").unwrap().1,
+            Rewrite(
+                app("+", vec![v("A"), v("B")]),
+                app("+", vec![v("B"), v("A")]),
+                vec![]
+            )
+        );
+        assert_eq!(
+            entry("f(x + y) = z.").unwrap().1,
+            Fact(Eq(
+                is_ground(&app("f", vec![app("+", vec![c("x"), c("y")])])).unwrap(),
+                GroundTerm::Apply("z".to_string(), vec![])
+            ))
+        );
+        assert_eq!(
+            term2("f x + g y").unwrap().1,
+            app("+", vec![app("f", vec![c("x")]), app("g", vec![c("y")])])
+        );
+
+        let file = ":- op(700, xfx, mod).\n:- op(300, xfx, ~>).\n?- X mod 2 = a ~> b + c.";
+        let es = parse_file(file.to_string()).unwrap();
+        assert_eq!(
+            es[0],
+            Directive(Directive::Op(700, OpType::Xfx, "mod".to_string()))
+        );
+        assert_eq!(
+            es[2],
+            Query(vec![Cond::Pos(Eq(
+                app("mod", vec![v("X"), Lit(Literal::Int(2))]),
+                app("+", vec![app("~>", vec![c("a"), c("b")]), c("c")])
+            ))])
+        );
+        assert_eq!(
+            form("forall x, x mod y = z").unwrap().1,
+            ForAll(
+                vec!["x".to_string()],
+                Box::new(Atom(Eq(app("mod", vec![c("x"), c("y")]), c("z"))))
+            )
+        );
+        // Reserved symbols are never operators
+        assert!(op_decl("op(700, xfx, <-)").is_err());
+        declare_op(0, OpType::Xfx, "mod");
+        assert_eq!(infix_op("mod"), None);
+        // The next file starts from the default operators
+        assert!(infix_op("~>").is_some());
+        assert!(parse_file("?- a ~> b.".to_string()).is_err());
+        assert_eq!(infix_op("~>"), None);
+    }
+    #[test]
+    fn names() {
+        let app = |f: &str, args: Vec<Term>| Apply(f.to_string(), args);
+        let c = |x: &str| app(x, vec![]);
+        let v = |x: &str| Var(x.to_string());
+        assert_eq!(
+            term("has_child(X_1, _, x')").unwrap().1,
+            app("has_child", vec![v("X_1"), v("_"), c("x'")])
+        );
+        assert_eq!(term("_Foo").unwrap().1, v("_Foo"));
+        assert_eq!(term("'+'(a, b)").unwrap().1, term("a + b").unwrap().1);
+        assert_eq!(
+            term("'hello world'('it\\'s')").unwrap().1,
+            app("hello world", vec![c("it's")])
+        );
+        assert_eq!(term("''").unwrap().1, c(""));
+        assert!(term("'unterminated").is_err());
+        assert_eq!(
+            form("forall x_1, p_q x_1").unwrap().1,
+            ForAll(
+                vec!["x_1".to_string()],
+                Box::new(Atom(Bare(app("p_q", vec![c("x_1")]))))
+            )
+        );
+        assert!(entry("Axiom a_is_f: a = f.").is_ok());
+        assert!(entry("foo(_).").is_err());
+        for name in &[
+            "foo",
+            "x'",
+            "+",
+            "hello world",
+            "it's",
+            "",
+            "(",
+            "<-",
+            "a-b",
+        ] {
+            assert_eq!(term(&quote_atom(name)).unwrap(), ("", c(name)));
+        }
+    }
+    #[test]
+    fn literals() {
+        let int = |i: i64| Lit(Literal::Int(i));
+        assert_eq!(
+            term("f(42, \"a \\\"b\\\"\", x2)").unwrap().1,
+            Apply(
+                "f".to_string(),
+                vec![
+                    int(42),
+                    Lit(Literal::Str("a \"b\"".to_string())),
+                    Apply("x2".to_string(), vec![])
+                ]
+            )
+        );
+        assert_eq!(term("-1").unwrap().1, Apply("-".to_string(), vec![int(1)]));
+        let big = "123456789012345678901234567890";
+        assert_eq!(
+            term(big).unwrap().1,
+            Lit(Literal::Rat(big.parse().unwrap()))
+        );
+        assert_eq!(
+            term2("f 3").unwrap().1,
+            Apply("f".to_string(), vec![int(3)])
+        );
+        for l in &["7", "-7", "1/3", big, "\"a\\nb\""] {
+            let l: Literal = l.parse().unwrap();
+            assert_eq!(l.to_string().parse(), Ok(l));
+        }
+        assert_eq!("4/2".parse(), Ok(Literal::Int(2)));
+        assert_eq!(quote_atom("42"), "'42'");
+    }
+    #[test]
     fn includetest() {
         let f = "foo.pl".to_string();
         assert_eq!(
             entry(":-include(foo.pl).").unwrap().1,
             Directive(Directive::Include(f.clone()))
         );
+        assert_eq!(
+            entry(":- include(\"foo.pl\").").unwrap().1,
+            Directive(Directive::Include(f.clone()))
+        );
+        assert_eq!(
+            entry(":- include('foo.pl').").unwrap().1,
+            Directive(Directive::Include(f))
+        );
+    }
+
+    #[test]
+    fn limits() {
+        assert_eq!(
+            entry(":- node_limit(100000).").unwrap().1,
+            Directive(Directive::NodeLimit(100_000))
+        );
+        assert_eq!(
+            entry(":- iter_limit( 50 ).").unwrap().1,
+            Directive(Directive::IterLimit(50))
+        );
+        assert_eq!(
+            entry(":- time_limit(2.5).").unwrap().1,
+            Directive(Directive::TimeLimit(2.5))
+        );
+        assert!(entry(":- time_limit(fast).").is_err());
+        // Too big for a Duration
+        let huge = format!(":- time_limit(1{}).", "0".repeat(400));
+        assert!(entry(&huge).is_err());
+    }
+
+    #[test]
+    fn runs() {
+        assert_eq!(entry(":- run.").unwrap().1, Directive(Directive::Run(None)));
+        assert_eq!(
+            entry(":- run(2).").unwrap().1,
+            Directive(Directive::Run(Some(2)))
+        );
+        assert_eq!(
+            entry(":- saturate.").unwrap().1,
+            Directive(Directive::Saturate)
+        );
+        assert_eq!(entry(":- push.").unwrap().1, Directive(Directive::Push));
+        assert_eq!(entry(":-pop .").unwrap().1, Directive(Directive::Pop));
+        assert_eq!(entry(":- clear.").unwrap().1, Directive(Directive::Clear));
+    }
+
+    #[test]
+    fn named_rules() {
+        let e = entry("comm :: plus(X, Y) <-> plus(Y, X).").unwrap().1;
+        assert!(
+            matches!(&e, Named(name, rule) if name == "comm" && matches!(**rule, BiRewrite(..)))
+        );
+        assert_eq!(e.to_string(), "comm :: plus(X, Y) <-> plus(Y, X).");
+        assert!(entry("f :: g(a).").is_err());
+        let run = |r: &str| Schedule::Run(r.to_string());
+        assert_eq!(
+            entry(":- schedule(seq(saturate(datalog), repeat(5, rewrites))).")
+                .unwrap()
+                .1,
+            Directive(Directive::Schedule(Schedule::Seq(vec![
+                Schedule::Saturate(Box::new(run("datalog"))),
+                Schedule::Repeat(5, Box::new(run("rewrites"))),
+            ])))
+        );
+        assert_eq!(
+            entry(":- disable('my rule').").unwrap().1.to_string(),
+            ":- disable('my rule')."
+        );
+        assert_eq!(
+            entry(":- match_limit(comm, 100).").unwrap().1,
+            Directive(Directive::MatchLimit("comm".to_string(), 100))
+        );
+        assert_eq!(
+            entry(":- ban_length( comm , 2 ).").unwrap().1,
+            Directive(Directive::BanLength("comm".to_string(), 2))
+        );
+    }
+
+    #[test]
+    fn negation() {
+        let e = entry("p(X) :- q(X), \\+ r(X), not s(X) = t.").unwrap().1;
+        match &e {
+            Clause(_, body) => {
+                assert!(matches!(body[1], Cond::Not(Bare(_))));
+                assert!(matches!(body[2], Cond::Not(Eq(..))));
+            }
+            _ => panic!("not a clause"),
+        }
+        assert_eq!(e.to_string(), "p(X) :- q(X), \\+ r(X), \\+ s(X) = t.");
+        let e = entry("f(X) <- g(X), not(X) = a.").unwrap().1;
+        assert!(matches!(&e, Rewrite(_, _, body) if matches!(body[0], Cond::Pos(_))));
+    }
+
+    #[test]
+    fn insert_query() {
+        let e = entry("?+ f(f(f(x))) = x, g(Y).").unwrap().1;
+        assert!(matches!(&e, InsertQuery(qs) if qs.len() == 2));
+        assert_eq!(e.to_string(), "?+ f(f(f(x))) = x, g(Y).");
+        assert!(matches!(entry("?- f(x).").unwrap().1, Query(_)));
+    }
+
+    #[test]
+    fn disjunction() {
+        let e = entry("r(X) :- s(X) ; e(Y, X), r(Y).").unwrap().1;
+        match &e {
+            Clause(_, body) => match &body[..] {
+                [Cond::Or(branches)] => {
+                    assert_eq!(branches.len(), 2);
+                    assert_eq!(branches[1].len(), 2);
+                }
+                _ => panic!("not one disjunction"),
+            },
+            _ => panic!("not a clause"),
+        }
+        assert_eq!(e.to_string(), "r(X) :- (s(X) ; e(Y, X), r(Y)).");
+        let e = entry("?- p(X), (q(X) ; \\+ r(X)).").unwrap().1;
+        assert!(matches!(&e, Query(qs) if qs.len() == 2 && matches!(qs[1], Cond::Or(_))));
+        assert_eq!(entry(&e.to_string()).unwrap().1, e);
     }
 }
 
@@ -0,0 +1,156 @@
+use egg::*;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// Which rewrite scheduler the Runner uses. `--scheduler simple|backoff`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SchedulerKind {
+    Simple,
+    Backoff,
+}
+
+impl FromStr for SchedulerKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "simple" => Ok(SchedulerKind::Simple),
+            "backoff" => Ok(SchedulerKind::Backoff),
+            _ => Err(format!(
+                "unknown scheduler {}, expected simple or backoff",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SchedulerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchedulerKind::Simple => write!(f, "simple"),
+            SchedulerKind::Backoff => write!(f, "backoff"),
+        }
+    }
+}
+
+// rule name -> (times banned, iterations banned)
+pub type Bans = Rc<RefCell<BTreeMap<String, (usize, usize)>>>;
+
+#[derive(Debug, Default)]
+struct RuleStats {
+    times_banned: usize,
+    banned_until: usize,
+}
+
+/// egg's BackoffScheduler, with a match limit and ban length per rule.
+/// Every ban is written down in `bans` so it can be reported after the run.
+pub struct Backoff {
+    match_limit: usize,
+    ban_length: usize,
+    rules: HashMap<String, (Option<usize>, Option<usize>)>,
+    stats: HashMap<String, RuleStats>,
+    bans: Bans,
+}
+
+impl Backoff {
+    pub fn new(match_limit: usize, ban_length: usize, bans: Bans) -> Self {
+        Backoff {
+            match_limit,
+            ban_length,
+            rules: HashMap::new(),
+            stats: HashMap::new(),
+            bans,
+        }
+    }
+
+    pub fn with_rules(self, rules: HashMap<String, (Option<usize>, Option<usize>)>) -> Self {
+        Backoff { rules, ..self }
+    }
+}
+
+impl<L: Language, N: Analysis<L>> RewriteScheduler<L, N> for Backoff {
+    // Skip ahead to when the next ban runs out, as egg does
+    fn can_stop(&mut self, iteration: usize) -> bool {
+        let banned: Vec<&mut RuleStats> = self
+            .stats
+            .values_mut()
+            .filter(|s| s.banned_until > iteration)
+            .collect();
+        match banned.iter().map(|s| s.banned_until - iteration).min() {
+            None => true,
+            Some(wait) => {
+                for s in banned {
+                    s.banned_until -= wait;
+                }
+                false
+            }
+        }
+    }
+
+    fn search_rewrite(
+        &mut self,
+        iteration: usize,
+        egraph: &EGraph<L, N>,
+        rewrite: &Rewrite<L, N>,
+    ) -> Vec<SearchMatches<L>> {
+        let name = rewrite.name().to_string();
+        let (match_limit, ban_length) = match self.rules.get(&name) {
+            Some((m, b)) => (m.unwrap_or(self.match_limit), b.unwrap_or(self.ban_length)),
+            None => (self.match_limit, self.ban_length),
+        };
+        let stats = self.stats.entry(name.clone()).or_default();
+        if iteration < stats.banned_until {
+            return vec![];
+        }
+        let threshold = match_limit
+            .checked_shl(stats.times_banned as u32)
+            .unwrap_or(usize::MAX);
+        let matches = rewrite.search(egraph);
+        let total: usize = matches.iter().map(|m| m.substs.len()).sum();
+        if total > threshold {
+            let length = ban_length
+                .checked_shl(stats.times_banned as u32)
+                .unwrap_or(usize::MAX);
+            stats.times_banned += 1;
+            stats.banned_until = iteration.saturating_add(length);
+            let mut bans = self.bans.borrow_mut();
+            let ban = bans.entry(name).or_default();
+            ban.0 += 1;
+            ban.1 = ban.1.saturating_add(length);
+            vec![]
+        } else {
+            matches
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bans() {
+        let mut egraph = EGraph::<SymbolLang, ()>::default();
+        egraph.add_expr(&"(f (f (f a)))".parse().unwrap());
+        egraph.rebuild();
+        let pat = |s: &str| s.parse::<Pattern<SymbolLang>>().unwrap();
+        let rw = Rewrite::new("unwrap", pat("(f ?x)"), pat("?x")).unwrap();
+        let bans = Bans::default();
+        let mut sched = Backoff::new(2, 3, bans.clone());
+        // 3 matches is over the limit of 2
+        assert!(sched.search_rewrite(0, &egraph, &rw).is_empty());
+        assert!(!RewriteScheduler::<SymbolLang, ()>::can_stop(&mut sched, 0));
+        // Banned until iteration 3, skipped ahead to 0. The limit is now 4
+        assert_eq!(sched.search_rewrite(0, &egraph, &rw).len(), 3);
+        assert_eq!(bans.borrow()["unwrap"], (1, 3));
+
+        let mut sched = Backoff::new(2, 3, Bans::default()).with_rules(
+            vec![("unwrap".to_string(), (Some(5), None))]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(sched.search_rewrite(0, &egraph, &rw).len(), 3);
+    }
+}
@@ -0,0 +1,476 @@
+// Reading the equational / Horn fragment of SMT-LIB2 into egglog entries.
+// Sorts are ignored, only the shape of the assertions matters.
+use crate::types::*;
+use nom::{
+    branch::alt,
+    bytes::complete::{is_not, tag, take_while, take_while1},
+    character::complete::{char, multispace1, not_line_ending},
+    combinator::{map, value},
+    multi::many0,
+    sequence::{delimited, preceded},
+    IResult,
+};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use std::collections::HashSet;
+use std::fmt;
+use EqWrap::*;
+use Term::*;
+
+#[derive(Debug, PartialEq, Clone)]
+enum Sexp {
+    Atom(String),
+    Str(String),
+    List(Vec<Sexp>),
+}
+use Sexp::*;
+
+impl fmt::Display for Sexp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Atom(a) => write!(f, "{}", a),
+            Str(s) => write!(f, "\"{}\"", s.replace('"', "\"\"")),
+            List(xs) => {
+                let xs: Vec<String> = xs.iter().map(|x| x.to_string()).collect();
+                write!(f, "({})", xs.join(" "))
+            }
+        }
+    }
+}
+
+fn sp(input: &str) -> IResult<&str, ()> {
+    value(
+        (),
+        many0(alt((multispace1, preceded(char(';'), not_line_ending)))),
+    )(input)
+}
+
+fn symbol(input: &str) -> IResult<&str, Sexp> {
+    alt((
+        map(delimited(char('|'), is_not("|"), char('|')), |s: &str| {
+            Atom(s.to_string())
+        }),
+        map(
+            take_while1(|c: char| c.is_alphanumeric() || "~!@$%^&*_-+=<>.?/:'".contains(c)),
+            |s: &str| Atom(s.to_string()),
+        ),
+    ))(input)
+}
+
+// "" is the only escape in an SMT-LIB string
+fn string(input: &str) -> IResult<&str, Sexp> {
+    let (mut rest, _) = char('"')(input)?;
+    let mut s = String::new();
+    loop {
+        let (r, chunk) = take_while(|c| c != '"')(rest)?;
+        s.push_str(chunk);
+        match tag::<_, _, ()>("\"\"")(r) {
+            Ok((r, _)) => {
+                s.push('"');
+                rest = r;
+            }
+            Err(_) => {
+                let (r, _) = char('"')(r)?;
+                return Ok((r, Str(s)));
+            }
+        }
+    }
+}
+
+fn sexp(input: &str) -> IResult<&str, Sexp> {
+    preceded(
+        sp,
+        alt((
+            map(
+                delimited(char('('), many0(sexp), preceded(sp, char(')'))),
+                List,
+            ),
+            string,
+            symbol,
+        )),
+    )(input)
+}
+
+/// A script read as egglog entries. `check_sats` is the number of entries asserted
+/// before each `(check-sat)`.
+#[derive(Debug, PartialEq)]
+pub struct Script {
+    pub entries: Vec<Entry>,
+    pub check_sats: Vec<usize>,
+}
+
+fn unsupported<T>(what: &str, s: &Sexp) -> Result<T, String> {
+    Err(format!("unsupported {}: {}", what, s))
+}
+
+fn number(a: &str) -> Option<Literal> {
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    match a.find('.') {
+        None if digits(a) => Some(Literal::number(BigRational::from_integer(a.parse().ok()?))),
+        Some(i) if digits(&a[..i]) && digits(&a[i + 1..]) => {
+            let frac = &a[i + 1..];
+            let n: BigInt = format!("{}{}", &a[..i], frac).parse().ok()?;
+            let d = num_traits::pow(BigInt::from(10), frac.len());
+            Some(Literal::number(BigRational::new(n, d)))
+        }
+        _ => None,
+    }
+}
+
+fn term(s: &Sexp, vars: &HashSet<String>) -> Result<Term, String> {
+    match s {
+        Atom(a) if vars.contains(a) => Ok(Var(a.clone())),
+        Atom(a) => Ok(match number(a) {
+            Some(l) => Lit(l),
+            None => Apply(a.clone(), vec![]),
+        }),
+        Str(s) => Ok(Lit(Literal::Str(s.clone()))),
+        List(xs) => match xs.split_first() {
+            Some((Atom(f), args))
+                if !args.is_empty() && !["let", "!", "_"].contains(&f.as_str()) =>
+            {
+                let args: Result<Vec<_>, _> = args.iter().map(|a| term(a, vars)).collect();
+                Ok(Apply(f.clone(), args?))
+            }
+            _ => unsupported("term", s),
+        },
+    }
+}
+
+fn head(s: &Sexp) -> Option<(&str, &[Sexp])> {
+    match s {
+        List(xs) => match xs.split_first() {
+            Some((Atom(f), args)) => Some((f.as_str(), args)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// A literal as equations. Boolean atoms are equations with true.
+fn atom(s: &Sexp, vars: &HashSet<String>) -> Result<Vec<EqWrap<Term>>, String> {
+    match head(s) {
+        Some(("=", args)) if args.len() >= 2 => {
+            let ts: Result<Vec<_>, _> = args.iter().map(|a| term(a, vars)).collect();
+            Ok(ts?
+                .windows(2)
+                .map(|w| Eq(w[0].clone(), w[1].clone()))
+                .collect())
+        }
+        Some(("and", args)) => {
+            let mut eqs = vec![];
+            for a in args {
+                eqs.extend(atom(a, vars)?);
+            }
+            Ok(eqs)
+        }
+        Some(("not", _)) | Some(("or", _)) | Some(("=>", _)) | Some(("distinct", _)) => {
+            unsupported("literal", s)
+        }
+        _ => Ok(vec![Eq(term(s, vars)?, Apply("true".to_string(), vec![]))]),
+    }
+}
+
+// Strips `!` annotations, keeping the patterns
+fn annotated(s: &Sexp) -> (&Sexp, Vec<&Sexp>) {
+    match head(s) {
+        Some(("!", args)) if !args.is_empty() => {
+            let (body, mut pats) = annotated(&args[0]);
+            for kv in args[1..].windows(2) {
+                if kv[0] == Atom(":pattern".to_string()) {
+                    pats.push(&kv[1]);
+                }
+            }
+            (body, pats)
+        }
+        _ => (s, vec![]),
+    }
+}
+
+fn term_vars(t: &Term, vs: &mut HashSet<String>) {
+    match t {
+        Var(v) => {
+            vs.insert(v.clone());
+        }
+        Apply(_, args) => args.iter().for_each(|a| term_vars(a, vs)),
+        Lit(_) => (),
+    }
+}
+
+fn eqs_vars(eqs: &[EqWrap<Term>]) -> HashSet<String> {
+    let mut vs = HashSet::new();
+    for t in eqs.iter().flat_map(|eq| eq.terms()) {
+        term_vars(t, &mut vs);
+    }
+    vs
+}
+
+// A negated assertion is a goal. The script is unsat if any of them is proven.
+fn goals(s: &Sexp, out: &mut Vec<Entry>) -> Result<(), String> {
+    let none = HashSet::new();
+    match head(s) {
+        Some(("or", args)) => {
+            for a in args {
+                goals(a, out)?;
+            }
+        }
+        _ => {
+            // The terms of a goal are in the e-graph, as they would be for an SMT solver
+            let eqs = atom(s, &none)?;
+            for eq in &eqs {
+                if let Eq(a, b) = eq {
+                    out.push(Entry::Fact(Bare(is_ground(a).unwrap())));
+                    out.push(Entry::Fact(Bare(is_ground(b).unwrap())));
+                }
+            }
+            out.push(Entry::Query(eqs.into_iter().map(Cond::Pos).collect()))
+        }
+    }
+    Ok(())
+}
+
+// `vars` are the variables bound by enclosing foralls and `pats` the patterns given for them.
+fn assertion(
+    s: &Sexp,
+    mut vars: HashSet<String>,
+    mut pats: Vec<EqWrap<Term>>,
+    out: &mut Vec<Entry>,
+) -> Result<(), String> {
+    let (body, ps) = annotated(s);
+    if ps.len() > 1 {
+        // Each pattern is an alternative trigger
+        for p in ps {
+            let one = List(vec![
+                Atom("!".to_string()),
+                body.clone(),
+                Atom(":pattern".to_string()),
+                p.clone(),
+            ]);
+            assertion(&one, vars.clone(), pats.clone(), out)?;
+        }
+        return Ok(());
+    }
+    for p in ps {
+        match p {
+            List(ts) => {
+                for t in ts {
+                    pats.push(Bare(term(t, &vars)?));
+                }
+            }
+            _ => return unsupported("pattern", p),
+        }
+    }
+    match head(body) {
+        Some(("forall", [List(bound), f])) => {
+            for b in bound {
+                match head(b) {
+                    Some((x, [_sort])) => vars.insert(x.to_string()),
+                    _ => return unsupported("binder", b),
+                };
+            }
+            assertion(f, vars, pats, out)
+        }
+        Some(("and", args)) => {
+            for a in args {
+                assertion(a, vars.clone(), pats.clone(), out)?;
+            }
+            Ok(())
+        }
+        Some(("not", [g])) if vars.is_empty() => goals(g, out),
+        Some(("distinct", args)) if vars.is_empty() => {
+            for (i, a) in args.iter().enumerate() {
+                for b in &args[i + 1..] {
+                    goals(
+                        &List(vec![Atom("=".to_string()), a.clone(), b.clone()]),
+                        out,
+                    )?;
+                }
+            }
+            Ok(())
+        }
+        Some(("=>", [hyp, concl])) => horn(atom(hyp, &vars)?, atom(concl, &vars)?, pats, s, out),
+        Some(("or", lits)) => {
+            // Horn clauses written as one positive literal and the rest negated
+            let mut hyps = vec![];
+            let mut concl = vec![];
+            for l in lits {
+                match head(l) {
+                    Some(("not", [h])) => hyps.extend(atom(h, &vars)?),
+                    _ => concl.push(l),
+                }
+            }
+            match concl.as_slice() {
+                [c] => horn(hyps, atom(c, &vars)?, pats, s, out),
+                _ => unsupported("non-Horn clause", s),
+            }
+        }
+        _ => {
+            for eq in atom(body, &vars)? {
+                if vars.is_empty() {
+                    horn(vec![], vec![eq], vec![], s, out)?;
+                } else {
+                    quantified_eq(eq, &pats, s, out)?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn horn(
+    mut hyps: Vec<EqWrap<Term>>,
+    concl: Vec<EqWrap<Term>>,
+    pats: Vec<EqWrap<Term>>,
+    s: &Sexp,
+    out: &mut Vec<Entry>,
+) -> Result<(), String> {
+    hyps.extend(pats);
+    if let Some(v) = eqs_vars(&concl).difference(&eqs_vars(&hyps)).next() {
+        return Err(format!(
+            "variable {} of the conclusion is not bound by the hypotheses or a pattern: {}",
+            v, s
+        ));
+    }
+    if !hyps.is_empty() {
+        let hyps = hyps.into_iter().map(Cond::Pos).collect();
+        out.push(Entry::Clause(concl, hyps));
+        return Ok(());
+    }
+    for eq in concl {
+        out.push(Entry::Fact(eq.map(|t| is_ground(&t).unwrap())));
+    }
+    Ok(())
+}
+
+// With a pattern that is one side of the equation, that side is the left of a rewrite.
+// Without one, a side that mentions every variable is used, the trigger an SMT solver would pick.
+fn quantified_eq(
+    eq: EqWrap<Term>,
+    pats: &[EqWrap<Term>],
+    s: &Sexp,
+    out: &mut Vec<Entry>,
+) -> Result<(), String> {
+    let (l, r) = match eq {
+        Eq(l, r) => (l, r),
+        _ => return unsupported("assertion", s),
+    };
+    let vars = |t: &Term| {
+        let mut vs = HashSet::new();
+        term_vars(t, &mut vs);
+        vs
+    };
+    let (lv, rv) = (vars(&l), vars(&r));
+    let trigger = |t: &Term| !matches!(t, Var(_));
+    out.push(match pats {
+        [Bare(p)] if *p == l && rv.is_subset(&lv) => Entry::Rewrite(r, l, vec![]),
+        [Bare(p)] if *p == r && lv.is_subset(&rv) => Entry::Rewrite(l, r, vec![]),
+        [] if trigger(&l) && rv.is_subset(&lv) => Entry::Rewrite(r, l, vec![]),
+        [] if trigger(&r) && lv.is_subset(&rv) => Entry::Rewrite(l, r, vec![]),
+        [] => {
+            return Err(format!(
+                "neither side of the equation can be used as a pattern, add a :pattern: {}",
+                s
+            ))
+        }
+        _ => return horn(vec![], vec![Eq(l, r)], pats.to_vec(), s, out),
+    });
+    Ok(())
+}
+
+/// Reads an SMT-LIB2 script. Declarations and options are skipped,
+/// and assertions outside the equational Horn fragment are errors.
+pub fn parse_smt2(input: &str) -> Result<Script, String> {
+    let mut script = Script {
+        entries: vec![],
+        check_sats: vec![],
+    };
+    let mut rest = input;
+    loop {
+        rest = sp(rest).map(|(r, _)| r).unwrap_or(rest);
+        if rest.is_empty() {
+            return Ok(script);
+        }
+        let (r, cmd) = sexp(rest).map_err(|_| {
+            let line = input[..input.len() - rest.len()].matches('\n').count() + 1;
+            format!("line {}: could not read s-expression", line)
+        })?;
+        rest = r;
+        match head(&cmd) {
+            Some(("assert", [f])) => assertion(f, HashSet::new(), vec![], &mut script.entries)?,
+            Some(("check-sat", [])) => script.check_sats.push(script.entries.len()),
+            Some(("set-logic", _))
+            | Some(("set-info", _))
+            | Some(("set-option", _))
+            | Some(("declare-sort", _))
+            | Some(("declare-fun", _))
+            | Some(("declare-const", _))
+            | Some(("get-info", _))
+            | Some(("exit", _)) => (),
+            _ => return unsupported("command", &cmd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+    use Entry::*;
+
+    fn entries(s: &str) -> Vec<Entry> {
+        parse_smt2(s).unwrap().entries
+    }
+
+    #[test]
+    fn translate() {
+        let e = |s: &str| parse_file(s.to_string()).unwrap().remove(0);
+        assert_eq!(
+            entries("(assert (= (f a) b)) (assert (p \"s\" 2))"),
+            vec![e("f(a) = b."), e("p(\"s\", 2) = true.")]
+        );
+        assert_eq!(
+            number("1.5"),
+            Some(Literal::Rat(BigRational::new(3.into(), 2.into())))
+        );
+        // The pattern picks the side to match on, otherwise the side with all the variables
+        assert_eq!(
+            entries(
+                "(assert (forall ((x S)) (! (= (f x) (g x)) :pattern ((g x)))))
+                 (assert (forall ((x S) (y S)) (= (h x) (k x y))))"
+            ),
+            vec![
+                Rewrite(
+                    Apply("f".into(), vec![Var("x".into())]),
+                    Apply("g".into(), vec![Var("x".into())]),
+                    vec![]
+                ),
+                Rewrite(
+                    Apply("h".into(), vec![Var("x".into())]),
+                    Apply("k".into(), vec![Var("x".into()), Var("y".into())]),
+                    vec![]
+                ),
+            ]
+        );
+        let x = || Var("x".into());
+        let p = |f: &str| Apply(f.into(), vec![x()]);
+        let t = || Apply("true".into(), vec![]);
+        assert_eq!(
+            entries("(assert (forall ((x S)) (or (not (p x)) (q x))))"),
+            vec![Clause(
+                vec![Eq(p("q"), t())],
+                vec![Cond::Pos(Eq(p("p"), t()))]
+            )]
+        );
+        let script =
+            parse_smt2("(assert (not (= a b))) (check-sat) (assert (distinct c d)) (check-sat)")
+                .unwrap();
+        assert_eq!(script.check_sats, vec![3, 6]);
+        assert_eq!(script.entries[2], e("?- a = b."));
+        assert_eq!(script.entries[5], e("?- c = d."));
+
+        assert!(parse_smt2("(assert (forall ((x S)) (= x (f x))))").is_ok());
+        assert!(parse_smt2("(assert (forall ((x S) (y S)) (= (f x) (g y))))").is_err());
+        assert!(parse_smt2("(assert (forall ((x S)) (=> (p a) (q x))))").is_err());
+        assert!(parse_smt2("(assert (exists ((x S)) (p x)))").is_err());
+    }
+}
@@ -0,0 +1,517 @@
+// Reading the unit equality and Horn fragments of TPTP cnf and fof problems into egglog entries.
+use crate::gensym::gensym;
+use crate::parser::{quoted, sp, ws};
+use crate::types::*;
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_while},
+    character::complete::{char, digit1, satisfy},
+    combinator::{map, map_res, opt, recognize},
+    multi::{many1, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, tuple},
+    IResult,
+};
+use std::collections::HashSet;
+use EqWrap::*;
+use Term::*;
+
+#[derive(Debug, PartialEq, Clone)]
+enum Fof {
+    Atom(EqWrap<Term>),
+    Not(Box<Fof>),
+    And(Vec<Fof>),
+    Or(Vec<Fof>),
+    Impl(Box<Fof>, Box<Fof>),
+    Iff(Box<Fof>, Box<Fof>),
+    ForAll(Vec<String>, Box<Fof>),
+    Exists(Vec<String>, Box<Fof>),
+}
+
+/// A `fof` or `cnf` formula. `role` is `axiom`, `conjecture`, `negated_conjecture` and so on.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Annotated {
+    pub name: String,
+    pub role: String,
+    form: Fof,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TptpInput {
+    Include(String, Option<Vec<String>>),
+    Formula(Annotated),
+}
+
+fn is_alnum(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Functors, constants and predicates. $ words are the defined ones like $true.
+fn word(input: &str) -> IResult<&str, String> {
+    alt((
+        map(
+            recognize(pair(
+                satisfy(|c| c.is_lowercase() || c == '$'),
+                take_while(is_alnum),
+            )),
+            String::from,
+        ),
+        |i| quoted('\'', i),
+    ))(input)
+}
+
+fn variable(input: &str) -> IResult<&str, String> {
+    map(
+        recognize(pair(satisfy(|c| c.is_uppercase()), take_while(is_alnum))),
+        String::from,
+    )(input)
+}
+
+fn term(input: &str) -> IResult<&str, Term> {
+    ws(alt((
+        map(variable, Var),
+        map_res(recognize(pair(opt(char('-')), digit1)), |n: &str| {
+            n.parse().map(Lit)
+        }),
+        // Distinct objects
+        map(|i| quoted('"', i), |s| Lit(Literal::Str(s))),
+        map(
+            pair(
+                word,
+                opt(delimited(
+                    preceded(sp, char('(')),
+                    separated_list1(char(','), term),
+                    char(')'),
+                )),
+            ),
+            |(f, args)| Apply(f, args.unwrap_or_default()),
+        ),
+    )))(input)
+}
+
+fn literal(input: &str) -> IResult<&str, Fof> {
+    let (input, a) = term(input)?;
+    let (input, eq) = opt(pair(alt((tag("!="), tag("="))), term))(input)?;
+    Ok((
+        input,
+        match eq {
+            None => Fof::Atom(Bare(a)),
+            Some(("=", b)) => Fof::Atom(Eq(a, b)),
+            Some((_, b)) => Fof::Not(Box::new(Fof::Atom(Eq(a, b)))),
+        },
+    ))
+}
+
+fn quantified(input: &str) -> IResult<&str, Fof> {
+    let (input, (q, vs, _, f)) = tuple((
+        alt((char('!'), char('?'))),
+        delimited(
+            ws(char('[')),
+            separated_list1(ws(char(',')), variable),
+            ws(char(']')),
+        ),
+        char(':'),
+        unitary,
+    ))(input)?;
+    Ok((
+        input,
+        if q == '!' {
+            Fof::ForAll(vs, Box::new(f))
+        } else {
+            Fof::Exists(vs, Box::new(f))
+        },
+    ))
+}
+
+fn unitary(input: &str) -> IResult<&str, Fof> {
+    ws(alt((
+        delimited(char('('), fof, char(')')),
+        map(preceded(char('~'), unitary), |f| Fof::Not(Box::new(f))),
+        quantified,
+        literal,
+    )))(input)
+}
+
+// & and | chain, the other connectives take two unitary formulas.
+fn fof(input: &str) -> IResult<&str, Fof> {
+    let (input, a) = unitary(input)?;
+    if let Ok((input, mut fs)) = many1(preceded(char('&'), unitary))(input) {
+        fs.insert(0, a);
+        return Ok((input, Fof::And(fs)));
+    }
+    if let Ok((input, mut fs)) = many1(preceded(char('|'), unitary))(input) {
+        fs.insert(0, a);
+        return Ok((input, Fof::Or(fs)));
+    }
+    let binary = alt((
+        tag("<=>"),
+        tag("=>"),
+        tag("<="),
+        tag("<~>"),
+        tag("~|"),
+        tag("~&"),
+    ));
+    let (input, rest) = opt(pair(binary, unitary))(input)?;
+    let not = |f| Fof::Not(Box::new(f));
+    Ok((
+        input,
+        match rest {
+            None => a,
+            Some((op, b)) => match op {
+                "<=>" => Fof::Iff(Box::new(a), Box::new(b)),
+                "=>" => Fof::Impl(Box::new(a), Box::new(b)),
+                "<=" => Fof::Impl(Box::new(b), Box::new(a)),
+                "<~>" => not(Fof::Iff(Box::new(a), Box::new(b))),
+                "~|" => not(Fof::Or(vec![a, b])),
+                _ => not(Fof::And(vec![a, b])),
+            },
+        },
+    ))
+}
+
+fn name(input: &str) -> IResult<&str, String> {
+    ws(alt((word, map(digit1, String::from))))(input)
+}
+
+// Source and useful info annotations are skipped, up to the closing paren.
+fn annotations(input: &str) -> IResult<&str, ()> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in input.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, '(') | (None, '[') => depth += 1,
+            (None, ')') if depth == 0 => return Ok((&input[i..], ())),
+            (None, ')') | (None, ']') => depth -= 1,
+            _ => (),
+        }
+    }
+    Err(nom::Err::Error(nom::error::make_error(
+        input,
+        nom::error::ErrorKind::TakeUntil,
+    )))
+}
+
+fn annotated(input: &str) -> IResult<&str, TptpInput> {
+    let (input, (_, _, name, _, role, _, form, _, _, _)) = tuple((
+        alt((tag("fof"), tag("cnf"))),
+        ws(char('(')),
+        name,
+        char(','),
+        ws(word),
+        char(','),
+        fof,
+        opt(preceded(char(','), annotations)),
+        char(')'),
+        ws(char('.')),
+    ))(input)?;
+    Ok((input, TptpInput::Formula(Annotated { name, role, form })))
+}
+
+fn include(input: &str) -> IResult<&str, TptpInput> {
+    let (input, (_, _, file, names, _, _)) = tuple((
+        tag("include"),
+        ws(char('(')),
+        ws(|i| quoted('\'', i)),
+        opt(preceded(
+            char(','),
+            delimited(
+                ws(char('[')),
+                separated_list0(char(','), name),
+                ws(char(']')),
+            ),
+        )),
+        char(')'),
+        ws(char('.')),
+    ))(input)?;
+    Ok((input, TptpInput::Include(file, names)))
+}
+
+/// Reads a TPTP file. Only `fof` and `cnf` formulas and includes are accepted.
+pub fn parse_tptp(input: &str) -> Result<Vec<TptpInput>, String> {
+    let mut inputs = vec![];
+    let mut rest = input;
+    loop {
+        rest = sp::<()>(rest).map(|(r, _)| r).unwrap_or(rest);
+        if rest.is_empty() {
+            return Ok(inputs);
+        }
+        match alt((include, annotated))(rest) {
+            Ok((r, i)) => {
+                inputs.push(i);
+                rest = r;
+            }
+            Err(_) => {
+                let line = input[..input.len() - rest.len()].matches('\n').count() + 1;
+                let found: String = rest.lines().next().unwrap_or("").chars().take(40).collect();
+                return Err(format!(
+                    "line {}: expected a fof, cnf or include, found `{}`",
+                    line, found
+                ));
+            }
+        }
+    }
+}
+
+fn term_vars(t: &Term, vs: &mut HashSet<String>) {
+    match t {
+        Var(v) => {
+            vs.insert(v.clone());
+        }
+        Apply(_, args) => args.iter().for_each(|a| term_vars(a, vs)),
+        Lit(_) => (),
+    }
+}
+
+fn eqs_vars(eqs: &[EqWrap<Term>]) -> HashSet<String> {
+    let mut vs = HashSet::new();
+    for t in eqs.iter().flat_map(|eq| eq.terms()) {
+        term_vars(t, &mut vs);
+    }
+    vs
+}
+
+fn atoms(f: &Fof) -> Result<Vec<EqWrap<Term>>, String> {
+    match f {
+        Fof::Atom(a) => Ok(vec![a.clone()]),
+        Fof::And(fs) => Ok(fs
+            .iter()
+            .map(atoms)
+            .collect::<Result<Vec<_>, _>>()?
+            .concat()),
+        _ => Err("expected a conjunction of atoms".to_string()),
+    }
+}
+
+// A universally quantified equation is a rewrite from a side that mentions every variable.
+fn unit(eq: EqWrap<Term>, out: &mut Vec<Entry>) -> Result<(), String> {
+    let vars = |t: &Term| {
+        let mut vs = HashSet::new();
+        term_vars(t, &mut vs);
+        vs
+    };
+    let (l, r) = match eq {
+        Eq(l, r) => (l, r),
+        MultiEq(_) => return Err("a chain of equations can't be a rewrite".to_string()),
+        Bare(a) => {
+            return match is_ground(&a) {
+                Some(a) => Ok(out.push(Entry::Fact(Bare(a)))),
+                None => {
+                    Err("a predicate can only hold for all arguments under hypotheses".to_string())
+                }
+            }
+        }
+    };
+    if let (Some(a), Some(b)) = (is_ground(&l), is_ground(&r)) {
+        out.push(Entry::Fact(Eq(a, b)));
+        return Ok(());
+    }
+    let (lv, rv) = (vars(&l), vars(&r));
+    let pattern = |t: &Term| !matches!(t, Var(_));
+    out.push(if pattern(&l) && pattern(&r) && lv == rv {
+        Entry::BiRewrite(l, r)
+    } else if pattern(&l) && rv.is_subset(&lv) {
+        Entry::Rewrite(r, l, vec![])
+    } else if pattern(&r) && lv.is_subset(&rv) {
+        Entry::Rewrite(l, r, vec![])
+    } else {
+        return Err("neither side of the equation mentions all of its variables".to_string());
+    });
+    Ok(())
+}
+
+fn horn(
+    body: Vec<EqWrap<Term>>,
+    heads: Vec<EqWrap<Term>>,
+    out: &mut Vec<Entry>,
+) -> Result<(), String> {
+    if body.is_empty() {
+        return heads.into_iter().try_for_each(|h| unit(h, out));
+    }
+    if let Some(v) = eqs_vars(&heads).difference(&eqs_vars(&body)).next() {
+        return Err(format!(
+            "variable {} of the conclusion is not in the hypotheses",
+            v
+        ));
+    }
+    let body = body.into_iter().map(Cond::Pos).collect();
+    out.push(Entry::Clause(heads, body));
+    Ok(())
+}
+
+// The terms of a goal go in the e-graph so that rewrites can fire on them.
+fn goal_terms(goals: &[EqWrap<Term>], out: &mut Vec<Entry>) {
+    for g in goals {
+        if let Eq(a, b) = g {
+            for t in &[a, b] {
+                if let Some(t) = is_ground(t) {
+                    out.push(Entry::Fact(Bare(t)));
+                }
+            }
+        }
+    }
+}
+
+fn axiom(f: &Fof, out: &mut Vec<Entry>) -> Result<(), String> {
+    match f {
+        Fof::ForAll(_, f) => axiom(f, out),
+        Fof::And(fs) => fs.iter().try_for_each(|f| axiom(f, out)),
+        Fof::Impl(hyp, conc) => horn(atoms(hyp)?, atoms(conc)?, out),
+        Fof::Or(_) | Fof::Atom(_) | Fof::Not(_) => {
+            let lits = match f {
+                Fof::Or(lits) => lits.clone(),
+                _ => vec![f.clone()],
+            };
+            let mut neg = vec![];
+            let mut pos = vec![];
+            for l in lits {
+                match l {
+                    Fof::Not(a) => neg.extend(atoms(&a)?),
+                    a => pos.extend(atoms(&a)?),
+                }
+            }
+            match pos.len() {
+                // A clause of only negative literals is refuted by proving its atoms
+                0 => {
+                    goal_terms(&neg, out);
+                    out.push(Entry::Query(neg.into_iter().map(Cond::Pos).collect()));
+                    Ok(())
+                }
+                1 => horn(neg, pos, out),
+                _ => Err("a clause with more than one positive literal is not Horn".to_string()),
+            }
+        }
+        _ => Err("only unit equalities and Horn clauses are supported".to_string()),
+    }
+}
+
+fn skolemize(t: &Term, sk: &[(String, String)]) -> Term {
+    match t {
+        Var(v) => match sk.iter().find(|(x, _)| x == v) {
+            Some((_, c)) => Apply(c.clone(), vec![]),
+            None => Var(v.clone()),
+        },
+        Apply(f, args) => Apply(f.clone(), args.iter().map(|a| skolemize(a, sk)).collect()),
+        Lit(l) => Lit(l.clone()),
+    }
+}
+
+// Universally quantified variables become fresh constants, existential ones are solved for.
+fn conjecture(f: &Fof, sk: &mut Vec<(String, String)>, out: &mut Vec<Entry>) -> Result<(), String> {
+    match f {
+        Fof::ForAll(vs, f) if !sk.iter().any(|(x, _)| vs.contains(x)) => {
+            sk.extend(vs.iter().map(|v| (v.clone(), gensym(v))));
+            conjecture(f, sk, out)
+        }
+        Fof::Exists(vs, f) => {
+            let goals: Vec<EqWrap<Term>> = atoms(f)?
+                .into_iter()
+                .map(|g| g.map(|t| skolemize(&t, sk)))
+                .collect();
+            goal_terms(&goals, out);
+            // Formulas name their variables as constants
+            let named = |t: &Term| {
+                let mut t = t.clone();
+                for v in vs {
+                    t = skolemize(&t, &[(v.clone(), v.clone())]);
+                }
+                t
+            };
+            let atoms: Vec<Formula> = goals
+                .iter()
+                .map(|g| Formula::Atom(g.clone().map(|t| named(&t))))
+                .collect();
+            let body = match atoms.len() {
+                1 => atoms.into_iter().next().unwrap(),
+                _ => Formula::Conj(atoms),
+            };
+            out.push(Entry::Goal(if vs.is_empty() {
+                body
+            } else {
+                Formula::Exists(vs.clone(), Box::new(body))
+            }));
+            Ok(())
+        }
+        f => conjecture(&Fof::Exists(vec![], Box::new(f.clone())), sk, out),
+    }
+}
+
+impl Annotated {
+    pub fn is_conjecture(&self) -> bool {
+        self.role == "conjecture"
+    }
+
+    /// The formula as egglog entries. A conjecture is a goal, and a negated conjecture or axiom
+    /// that is all negative literals is a query whose answer refutes it.
+    pub fn entries(&self) -> Result<Vec<Entry>, String> {
+        let mut out = vec![];
+        match self.role.as_str() {
+            "conjecture" => conjecture(&self.form, &mut vec![], &mut out),
+            "axiom" | "hypothesis" | "definition" | "assumption" | "lemma" | "theorem"
+            | "corollary" | "negated_conjecture" | "plain" => axiom(&self.form, &mut out),
+            role => Err(format!("unsupported role {}", role)),
+        }
+        .map_err(|e| format!("{}: {}", self.name, e))?;
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+
+    fn entries(s: &str) -> Result<Vec<Entry>, String> {
+        let mut out = vec![];
+        for i in parse_tptp(s)? {
+            if let TptpInput::Formula(a) = i {
+                out.extend(a.entries()?);
+            }
+        }
+        Ok(out)
+    }
+
+    #[test]
+    fn tptp() {
+        let es = |s: &str| parse_file(s.to_string()).unwrap();
+        assert_eq!(
+            parse_tptp("% comment\ninclude('Axioms/GRP004-0.ax').\ninclude('a.ax', [x, y]).")
+                .unwrap(),
+            vec![
+                TptpInput::Include("Axioms/GRP004-0.ax".to_string(), None),
+                TptpInput::Include(
+                    "a.ax".to_string(),
+                    Some(vec!["x".to_string(), "y".to_string()])
+                ),
+            ]
+        );
+        assert_eq!(
+            entries(
+                "cnf(left_identity, axiom, multiply(identity, X) = X).
+                 cnf(associativity, axiom, multiply(multiply(X, Y), Z) = multiply(X, multiply(Y, Z)), file('x', y)).
+                 fof(trans, axiom, ![X, Y, Z]: ((le(X, Y) & le(Y, Z)) => le(X, Z))).
+                 cnf(c, axiom, ~ le(X, Y) | le(s(X), s(Y))).
+                 cnf(prove, negated_conjecture, a != b)."
+            )
+            .unwrap(),
+            es("X <- multiply(identity, X).
+                multiply(multiply(X, Y), Z) <-> multiply(X, multiply(Y, Z)).
+                le(X, Z) :- le(X, Y), le(Y, Z).
+                le(s(X), s(Y)) :- le(X, Y).
+                a. b.
+                ?- a = b.")
+        );
+        assert_eq!(
+            entries("fof(g, conjecture, ?[X]: f(X) = a).").unwrap(),
+            vec![
+                Entry::Fact(Bare(GroundTerm::Apply("a".to_string(), vec![]))),
+                es("|- exists X, f X = a.").remove(0)
+            ]
+        );
+        assert!(entries("cnf(c, axiom, p(X) | q(X)).").is_err());
+        assert!(entries("fof(c, axiom, ![X]: p(X)).").is_err());
+        assert!(entries("cnf(c, axiom, p(X) | ~q(Y)).").is_err());
+        assert!(parse_tptp("tff(c, axiom, p).").is_err());
+    }
+}
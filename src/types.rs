@@ -1,67 +1,193 @@
+use crate::lang::*;
+use crate::parser::*;
 use egg::*;
+use num_rational::BigRational;
+use num_traits::ToPrimitive;
 use std::fmt;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Term {
     Var(String),
     Apply(String, Vec<Term>),
+    Lit(Literal),
 }
 use Term::*;
 
+// Prints in the syntax the parser reads, using the operator table and the fewest parentheses
+// that read back the same. `curried` is the formula syntax `f x (g y)` rather than `f(x, g(y))`.
+fn show_term(t: &Term, max: usize, curried: bool) -> String {
+    let (f, args) = match t {
+        Var(v) => return v.clone(),
+        Lit(l) => return l.to_string(),
+        Apply(f, args) => (f.as_str(), args),
+    };
+    let (s, p) = match (args.len(), infix_op(f), prefix_op(f), postfix_op(f)) {
+        (2, Some((p, typ)), _, _) => {
+            let (lmax, rmax) = match typ {
+                OpType::Xfy => (p - 1, p),
+                OpType::Yfx => (p, p - 1),
+                _ => (p - 1, p - 1),
+            };
+            let (a, b) = (
+                show_term(&args[0], lmax, curried),
+                show_term(&args[1], rmax, curried),
+            );
+            (format!("{} {} {}", a, f, b), p)
+        }
+        (1, _, Some((p, typ)), _) => {
+            let a = show_term(&args[0], if typ == OpType::Fy { p } else { p - 1 }, curried);
+            // Keep the operator from gluing onto the argument, as in `- -x` or `not x`.
+            let glued = f.chars().all(is_symbol_char)
+                && !a.starts_with(is_symbol_char)
+                && !a.starts_with('(');
+            (format!("{}{}{}", f, if glued { "" } else { " " }, a), p)
+        }
+        (1, _, _, Some((p, typ))) => {
+            let a = show_term(&args[0], if typ == OpType::Yf { p } else { p - 1 }, curried);
+            (format!("{} {}", a, f), p)
+        }
+        (0, ..) => return quote_atom(f),
+        _ if curried => {
+            let args: Vec<String> = args
+                .iter()
+                .map(|a| match a {
+                    Apply(c, b) if b.is_empty() => quote_atom(c),
+                    Lit(Literal::Int(i)) if *i >= 0 => i.to_string(),
+                    Lit(Literal::Str(_)) | Var(_) => show_term(a, 0, true),
+                    _ => format!("({})", show_term(a, 1200, true)),
+                })
+                .collect();
+            (format!("{} {}", quote_atom(f), args.join(" ")), 0)
+        }
+        _ => {
+            let args: Vec<String> = args.iter().map(|a| show_term(a, 999, false)).collect();
+            return format!("{}({})", quote_atom(f), args.join(", "));
+        }
+    };
+    if p > max {
+        format!("({})", s)
+    } else {
+        s
+    }
+}
+
 impl fmt::Display for Term {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", show_term(self, 1200, false))
+    }
+}
+
+// Integers that don't fit in an i64 are kept as big rationals.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub enum Literal {
+    Int(i64),
+    Rat(BigRational),
+    Str(String),
+}
+
+impl Literal {
+    // The canonical form of a number. Integers are Int whenever they fit.
+    pub fn number(r: BigRational) -> Literal {
+        match r.to_integer().to_i64() {
+            Some(i) if r.is_integer() => Literal::Int(i),
+            _ => Literal::Rat(r),
+        }
+    }
+
+    pub fn as_number(&self) -> Option<BigRational> {
         match self {
-            Var(v) => write!(f, "?{}", v),
-
-            Apply(g, args) => {
-                write!(f, "{}(", g)?;
-                for a in args {
-                    write!(f, "{},", a)?;
-                }
-                write!(f, ")")
-            }
+            Literal::Int(i) => Some(BigRational::from_integer((*i).into())),
+            Literal::Rat(r) => Some(r.clone()),
+            Literal::Str(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Int(i) => write!(f, "{}", i),
+            Literal::Rat(r) => write!(f, "{}", r),
+            Literal::Str(s) => write!(f, "{}", quote_string(s)),
+        }
+    }
+}
+
+// Needed by define_language. Accepts what Display produces.
+impl std::str::FromStr for Literal {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(i) = s.parse() {
+            Ok(Literal::Int(i))
+        } else if let Ok(r) = s.parse() {
+            Ok(Literal::number(r))
+        } else if let Ok(("", s)) = string_lit(s) {
+            Ok(Literal::Str(s))
+        } else {
+            Err(format!("{} is not a literal", s))
         }
     }
 }
 
 // There is an argument to be made that I should directly be using RecExpr for groundterm and Pattern for Term
 #[derive(Debug, PartialEq)]
-pub struct GroundTerm {
-    pub head: String,
-    pub args: Vec<GroundTerm>,
+pub enum GroundTerm {
+    Apply(String, Vec<GroundTerm>),
+    Lit(Literal),
 }
 
-impl fmt::Display for GroundTerm {
-    fn fmt(&self, buf: &mut fmt::Formatter) -> fmt::Result {
-        if self.args.len() == 0 {
-            write!(buf, "{}", self.head)
-        } else {
-            write!(buf, "{}(", self.head)?;
-            let mut iter = self.args.iter();
-            if let Some(item) = iter.next() {
-                write!(buf, "{}", item)?;
-                for item in iter {
-                    write!(buf, ", {}", item)?;
-                }
+impl GroundTerm {
+    pub fn to_term(&self) -> Term {
+        match self {
+            GroundTerm::Lit(l) => Lit(l.clone()),
+            GroundTerm::Apply(f, args) => {
+                Apply(f.clone(), args.iter().map(|a| a.to_term()).collect())
             }
-            write!(buf, ")")
         }
     }
 }
 
+impl fmt::Display for GroundTerm {
+    fn fmt(&self, buf: &mut fmt::Formatter) -> fmt::Result {
+        write!(buf, "{}", self.to_term())
+    }
+}
+
 // toplevel of term is eq only
 #[derive(Debug, PartialEq, Clone)]
 pub enum EqWrap<T> {
     Eq(T, T),
     Bare(T),
+    MultiEq(Vec<T>), // A = B = C = D. Always at least three terms.
 }
-// We could also consider MultiEq { terms : Vec<T> } for more than one A= B = C = D
 
 impl<T> EqWrap<T> {
     pub fn map<U, F: Fn(T) -> U>(self, f: F) -> EqWrap<U> {
         match self {
             EqWrap::Bare(x) => EqWrap::Bare(f(x)),
             EqWrap::Eq(a, b) => EqWrap::Eq(f(a), f(b)),
+            EqWrap::MultiEq(ts) => EqWrap::MultiEq(ts.into_iter().map(f).collect()),
+        }
+    }
+
+    /// Builds the equation `t1 = ... = tn`.
+    pub fn chain(mut ts: Vec<T>) -> EqWrap<T> {
+        match ts.len() {
+            1 => EqWrap::Bare(ts.remove(0)),
+            2 => {
+                let b = ts.pop().unwrap();
+                EqWrap::Eq(ts.pop().unwrap(), b)
+            }
+            _ => EqWrap::MultiEq(ts),
+        }
+    }
+
+    /// The terms of the equation in order.
+    pub fn terms(&self) -> Vec<&T> {
+        match self {
+            EqWrap::Bare(x) => vec![x],
+            EqWrap::Eq(a, b) => vec![a, b],
+            EqWrap::MultiEq(ts) => ts.iter().collect(),
         }
     }
 }
@@ -74,13 +200,114 @@ where
         match self {
             EqWrap::Eq(a, b) => write!(f, "{} = {}", a, b),
             EqWrap::Bare(v) => write!(f, "{}", v),
+            EqWrap::MultiEq(ts) => {
+                let ts: Vec<String> = ts.iter().map(|t| t.to_string()).collect();
+                write!(f, "{}", ts.join(" = "))
+            }
         }
     }
 }
 
+// A condition in a clause body, rewrite guard or query
+#[derive(Debug, PartialEq, Clone)]
+pub enum Cond {
+    Pos(EqWrap<Term>),
+    Not(EqWrap<Term>),  // \+ p(X) or not p(X), negation as failure
+    Or(Vec<Vec<Cond>>), // (a, b ; c), each branch a conjunction
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cond::Pos(e) => write!(f, "{}", e),
+            Cond::Not(e) => write!(f, "\\+ {}", e),
+            Cond::Or(branches) => {
+                let branches: Vec<String> = branches.iter().map(|b| comma_sep(b)).collect();
+                write!(f, "({})", branches.join(" ; "))
+            }
+        }
+    }
+}
+
+/// Operator types as in Prolog's `op/3`. `x` is an argument of strictly lower priority,
+/// `y` of lower or equal priority, `f` the operator itself.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OpType {
+    Xfx,
+    Xfy,
+    Yfx,
+    Fy,
+    Fx,
+    Xf,
+    Yf,
+}
+
+impl OpType {
+    pub fn is_infix(&self) -> bool {
+        matches!(self, OpType::Xfx | OpType::Xfy | OpType::Yfx)
+    }
+    pub fn is_prefix(&self) -> bool {
+        matches!(self, OpType::Fy | OpType::Fx)
+    }
+    pub fn is_postfix(&self) -> bool {
+        matches!(self, OpType::Xf | OpType::Yf)
+    }
+}
+
+impl fmt::Display for OpType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            OpType::Xfx => "xfx",
+            OpType::Xfy => "xfy",
+            OpType::Yfx => "yfx",
+            OpType::Fy => "fy",
+            OpType::Fx => "fx",
+            OpType::Xf => "xf",
+            OpType::Yf => "yf",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Directive {
     Include(String),
+    Op(usize, OpType, String), // priority, type, name
+    NodeLimit(usize),
+    IterLimit(usize),
+    TimeLimit(f64),     // seconds
+    Run(Option<usize>), // iterations, or up to the iter limit
+    Saturate,
+    Push,
+    Pop,
+    Clear,
+    Ruleset(String), // rules after this go in the ruleset
+    Schedule(Schedule),
+    Disable(String),
+    MatchLimit(String, usize), // rule name, limit for the backoff scheduler
+    BanLength(String, usize),
+}
+
+// :- schedule(seq(saturate(datalog), repeat(5, rewrites))).
+#[derive(Debug, PartialEq, Clone)]
+pub enum Schedule<R = String> {
+    Run(R), // one iteration of a ruleset
+    Repeat(usize, Box<Schedule<R>>),
+    Saturate(Box<Schedule<R>>),
+    Seq(Vec<Schedule<R>>),
+}
+
+impl<R> Schedule<R> {
+    pub fn try_map<S, E>(&self, f: &mut impl FnMut(&R) -> Result<S, E>) -> Result<Schedule<S>, E> {
+        Ok(match self {
+            Schedule::Run(r) => Schedule::Run(f(r)?),
+            Schedule::Repeat(n, s) => Schedule::Repeat(*n, Box::new(s.try_map(f)?)),
+            Schedule::Saturate(s) => Schedule::Saturate(Box::new(s.try_map(f)?)),
+            Schedule::Seq(ss) => {
+                Schedule::Seq(ss.iter().map(|s| s.try_map(f)).collect::<Result<_, _>>()?)
+            }
+        })
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -90,102 +317,216 @@ pub enum Formula {
     Disj(Vec<Formula>),
     ForAll(Vec<String>, Box<Formula>),
     Exists(Vec<String>, Box<Formula>),
+    ExistsUnique(Vec<String>, Box<Formula>), // exists!
     Atom(EqWrap<Term>),
 }
 
+// Binding strength of the connectives, loosest first. A quantifier extends as far right as it can,
+// so it needs parentheses anywhere but the top of a formula or the body of another quantifier.
+fn show_formula(f: &Formula, max: usize) -> String {
+    let join = |fs: &Vec<Formula>, sep: &str, p: usize| {
+        let fs: Vec<String> = fs.iter().map(|f| show_formula(f, p + 1)).collect();
+        (fs.join(sep), p)
+    };
+    let (s, p) = match f {
+        Formula::ForAll(vs, body) => (
+            format!("forall {}, {}", vs.join(" "), show_formula(body, 0)),
+            0,
+        ),
+        Formula::Exists(vs, body) => (
+            format!("exists {}, {}", vs.join(" "), show_formula(body, 0)),
+            0,
+        ),
+        Formula::ExistsUnique(vs, body) => (
+            format!("exists! {}, {}", vs.join(" "), show_formula(body, 0)),
+            0,
+        ),
+        Formula::Impl(hyp, conc) => (
+            format!("{} => {}", show_formula(hyp, 2), show_formula(conc, 1)),
+            1,
+        ),
+        Formula::Disj(fs) => join(fs, " \\/ ", 2),
+        Formula::Conj(fs) => join(fs, " /\\ ", 3),
+        Formula::Atom(eq) => {
+            let ts: Vec<String> = eq
+                .terms()
+                .iter()
+                .map(|t| show_term(t, 1200, true))
+                .collect();
+            (ts.join(" = "), 4)
+        }
+    };
+    if p < max {
+        format!("({})", s)
+    } else {
+        s
+    }
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", show_formula(self, 0))
+    }
+}
+
+impl fmt::Display for Directive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // Quoted only when a comment could be read into it
+            Directive::Include(file)
+                if file.contains("//")
+                    || !file
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || "._-/".contains(c)) =>
+            {
+                write!(f, "include({})", quote_string(file))
+            }
+            Directive::Include(file) => write!(f, "include({})", file),
+            Directive::Op(p, typ, name) => write!(f, "op({}, {}, {})", p, typ, name),
+            Directive::NodeLimit(n) => write!(f, "node_limit({})", n),
+            Directive::IterLimit(n) => write!(f, "iter_limit({})", n),
+            Directive::TimeLimit(secs) => write!(f, "time_limit({})", secs),
+            Directive::Run(None) => write!(f, "run"),
+            Directive::Run(Some(n)) => write!(f, "run({})", n),
+            Directive::Saturate => write!(f, "saturate"),
+            Directive::Push => write!(f, "push"),
+            Directive::Pop => write!(f, "pop"),
+            Directive::Clear => write!(f, "clear"),
+            Directive::Ruleset(name) => write!(f, "ruleset({})", quote_atom(name)),
+            Directive::Schedule(s) => write!(f, "schedule({})", s),
+            Directive::Disable(name) => write!(f, "disable({})", quote_atom(name)),
+            Directive::MatchLimit(name, n) => write!(f, "match_limit({}, {})", quote_atom(name), n),
+            Directive::BanLength(name, n) => write!(f, "ban_length({}, {})", quote_atom(name), n),
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Schedule::Run(name) => write!(f, "{}", quote_atom(name)),
+            Schedule::Repeat(n, s) => write!(f, "repeat({}, {})", n, s),
+            Schedule::Saturate(s) => write!(f, "saturate({})", s),
+            Schedule::Seq(ss) => write!(f, "seq({})", comma_sep(ss)),
+        }
+    }
+}
+
+fn comma_sep<T: fmt::Display>(xs: &[T]) -> String {
+    xs.iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Prints an entry the way the parser reads it, `.` included.
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Entry::Fact(a) => write!(f, "{}.", a),
+            Entry::Clause(head, body) => write!(f, "{} :- {}.", comma_sep(head), comma_sep(body)),
+            Entry::Rewrite(a, b, body) if body.is_empty() => write!(f, "{} <- {}.", a, b),
+            Entry::Rewrite(a, b, body) => write!(f, "{} <- {}, {}.", a, b, comma_sep(body)),
+            Entry::BiRewrite(a, b) => write!(f, "{} <-> {}.", a, b),
+            Entry::Directive(d) => write!(f, ":- {}.", d),
+            Entry::Query(qs) => write!(f, "?- {}.", comma_sep(qs)),
+            Entry::InsertQuery(qs) => write!(f, "?+ {}.", comma_sep(qs)),
+            Entry::Axiom(name, form) => write!(f, "Axiom {}: {}.", name, form),
+            Entry::Goal(form) => write!(f, "|- {}.", form),
+            Entry::Named(name, rule) => write!(f, "{} :: {}", quote_atom(name), rule),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Entry {
-    Clause(Vec<EqWrap<Term>>, Vec<EqWrap<Term>>),
+    Clause(Vec<EqWrap<Term>>, Vec<Cond>),
     Fact(EqWrap<GroundTerm>),
-    Rewrite(Term, Term, Vec<EqWrap<Term>>),
+    Rewrite(Term, Term, Vec<Cond>),
     BiRewrite(Term, Term),
     Directive(Directive),
-    Query(Vec<EqWrap<Term>>), // Should I only allow GroundTerm queries?
+    Query(Vec<Cond>),               // Should I only allow GroundTerm queries?
+    InsertQuery(Vec<EqWrap<Term>>), // ?+ inserts the ground subterms first
     Axiom(String, Formula),
     Goal(Formula),
+    Named(String, Box<Entry>), // name :: rule.
 }
 
 /* enum Directive {
-NodeLimit,
 ClassLimit
-TimeLimit,
-Include,
-Clear/Reset
 }
 
 */
 pub fn is_ground(t: &Term) -> Option<GroundTerm> {
     match t {
         Var(_) => None,
+        Lit(l) => Some(GroundTerm::Lit(l.clone())),
         Apply(f, args) => {
             let oargs: Option<Vec<GroundTerm>> = args.iter().map(is_ground).collect();
-            oargs.map(|args| GroundTerm {
-                head: f.to_string(),
-                args,
-            })
+            oargs.map(|args| GroundTerm::Apply(f.to_string(), args))
         }
     }
 }
 
-pub fn eid_of_groundterm(egraph: &mut EGraph<SymbolLang, ()>, t: &GroundTerm) -> Id {
-    let args = t
-        .args
-        .iter()
-        .map(|a| eid_of_groundterm(egraph, a))
-        .collect();
-    egraph.add(SymbolLang::new(t.head.clone(), args))
+pub fn eid_of_groundterm(egraph: &mut EGraph<EggLog, ConstFold>, t: &GroundTerm) -> Id {
+    match t {
+        GroundTerm::Lit(l) => egraph.add(EggLog::Lit(l.clone())),
+        GroundTerm::Apply(f, args) => {
+            let args = args.iter().map(|a| eid_of_groundterm(egraph, a)).collect();
+            egraph.add(EggLog::Fun(Symbol::from(f.as_str()), args))
+        }
+    }
 }
 
-fn recexpr_of_groundterm_aux(expr: &mut RecExpr<SymbolLang>, t: &GroundTerm) -> Id {
-    let expr_args = t
-        .args
-        .iter()
-        .map(|a| recexpr_of_groundterm_aux(expr, &a))
-        .collect();
-    expr.add(SymbolLang::new(t.head.clone(), expr_args))
+fn recexpr_of_groundterm_aux(expr: &mut RecExpr<EggLog>, t: &GroundTerm) -> Id {
+    match t {
+        GroundTerm::Lit(l) => expr.add(EggLog::Lit(l.clone())),
+        GroundTerm::Apply(f, args) => {
+            let expr_args = args
+                .iter()
+                .map(|a| recexpr_of_groundterm_aux(expr, &a))
+                .collect();
+            expr.add(EggLog::Fun(Symbol::from(f.as_str()), expr_args))
+        }
+    }
 }
 
-pub fn recexpr_of_groundterm(t: &GroundTerm) -> RecExpr<SymbolLang> {
+pub fn recexpr_of_groundterm(t: &GroundTerm) -> RecExpr<EggLog> {
     let mut expr = RecExpr::default();
     recexpr_of_groundterm_aux(&mut expr, t);
     expr
 }
 
-pub fn pattern_of_eqterm(t: &EqWrap<Term>) -> EqWrap<Pattern<SymbolLang>> {
+pub fn pattern_of_eqterm(t: &EqWrap<Term>) -> EqWrap<Pattern<EggLog>> {
     match t {
         EqWrap::Bare(x) => EqWrap::Bare(pattern_of_term(x)),
         EqWrap::Eq(x, y) => EqWrap::Eq(pattern_of_term(x), pattern_of_term(y)),
+        EqWrap::MultiEq(ts) => EqWrap::MultiEq(ts.iter().map(pattern_of_term).collect()),
     }
 }
-/*
-fn pattern_of_term(t : &Term) -> Pattern<SymbolLang> {
-    let mut ast = RecExpr::default();
-    fn worker(t : &Term){
+// Built directly rather than through an s-expression string, so any atom name survives.
+pub fn pattern_of_term(t: &Term) -> Pattern<EggLog> {
+    fn worker(ast: &mut PatternAst<EggLog>, t: &Term) -> Id {
         match t {
-            Var(x) => ast.add(ENodeOrVar::Var(Var(Symbol::from("x"))))
-            Apply(f,args) =>
-              let args = args.iter().map(worker).collect();
-             ast.add(ENodeOrVar::ENode( SymbolLang::new(f.clone(),args)))
-        }
-    }
-    worker(t);
-    let program = egg::machine::Program::compile_from_pat(&ast);
-    Pattern { ast, program }
-}
-*/
-pub fn sexp_of_term(t: &Term) -> String {
-    match t {
-        Var(x) => format!(" ?{} ", x),
-        Apply(f, args) => {
-            let args: String = args.iter().map(sexp_of_term).collect();
-            format!("({}{})", f, args)
+            // Each `_` gets its own variable. `#` can't appear in a parsed name so these never clash.
+            Var(x) if x == "_" => {
+                let v = format!("?_#{}", crate::gensym::fresh()).parse().unwrap();
+                ast.add(ENodeOrVar::Var(v))
+            }
+            Var(x) => ast.add(ENodeOrVar::Var(format!("?{}", x).parse().unwrap())),
+            Apply(f, args) => {
+                let args = args.iter().map(|a| worker(ast, a)).collect();
+                ast.add(ENodeOrVar::ENode(EggLog::Fun(
+                    Symbol::from(f.as_str()),
+                    args,
+                )))
+            }
+            Lit(l) => ast.add(ENodeOrVar::ENode(EggLog::Lit(l.clone()))),
         }
     }
-}
-
-// This sort of stuff is what From traits are for right?
-pub fn pattern_of_term(t: &Term) -> Pattern<SymbolLang> {
-    sexp_of_term(t).parse().unwrap()
+    let mut ast = RecExpr::default();
+    worker(&mut ast, t);
+    Pattern::new(ast)
 }
 
 /*
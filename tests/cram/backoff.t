@@ -0,0 +1,14 @@
+  $ $TESTDIR/run_test.sh backoff.pl --report
+  Results : 
+  -? (plus a (plus b (plus c d))) = (plus d (plus c (plus b a)))
+  [];
+  Banned rules:
+  assoc: banned 4 times for 30 iterations
+  assoc#2: banned 4 times for 30 iterations
+  
+  $ $TESTDIR/run_test.sh backoff.pl --report --scheduler simple
+  Results : 
+  -? (plus a (plus b (plus c d))) = (plus d (plus c (plus b a)))
+  [];
+  No rules were banned.
+  
@@ -0,0 +1,15 @@
+  $ $TESTDIR/run_test.sh chain.pl
+  Results : 
+  -? a = b = c
+  [];
+  -? (f a) = (g c) = (h b)
+  [];
+  -? (q b)
+  [];
+  -? (r c) = (s a)
+  [];
+  -? a = b = d
+  unknown.
+  -? (k c) = (l b) = (m a)
+  [];
+  
@@ -0,0 +1,39 @@
+  $ $TESTDIR/run_test.sh compound.pl
+  Results : 
+  -? (plus two one) = three
+  [];
+  -? (plus #x#0 #x#0) = (twice one)
+  [];
+  |- plus two one = three /\ (forall x, x = one => plus x x = twice one)  by /\R
+    |- plus two one = three  by -? (plus two one) = three: proved
+    |- forall x, x = one => plus x x = twice one  by forallR
+      |- '#x#0' = one => plus '#x#0' '#x#0' = twice one  by =>R
+        '#x#0' = one |- plus '#x#0' '#x#0' = twice one  by -? (plus #x#0 #x#0) = (twice one): proved
+  proved.
+  -? (plus one one) = three
+  unknown.
+  -? (plus one one) = (twice one)
+  [];
+  |- plus one one = three \/ plus one one = twice one  by \/R
+    |- plus one one = three  by -? (plus one one) = three: unknown
+    |- plus one one = twice one  by -? (plus one one) = (twice one): proved
+  proved.
+  -? (edge a ?y), (edge ?y c)
+  [?y = b];
+  -? (edge c a)
+  unknown.
+  |- (exists y, edge a y /\ edge y c) /\ edge c a  by /\R
+    |- exists y, edge a y /\ edge y c  by -? (edge a ?y), (edge ?y c): proved
+    |- edge c a  by -? (edge c a): unknown
+  unknown.
+  -? (plus #z#1 ?b) = three
+  [?b = two];
+  -? (edge #z#1 a)
+  unknown.
+  |- forall z, z = one => (exists b, plus z b = three) \/ edge z a  by forallR
+    |- '#z#1' = one => (exists b, plus '#z#1' b = three) \/ edge '#z#1' a  by =>R
+      '#z#1' = one |- (exists b, plus '#z#1' b = three) \/ edge '#z#1' a  by \/R
+        '#z#1' = one |- exists b, plus '#z#1' b = three  by -? (plus #z#1 ?b) = three: proved
+        '#z#1' = one |- edge '#z#1' a  by -? (edge #z#1 a): unknown
+  proved.
+  
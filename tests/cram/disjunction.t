@@ -0,0 +1,23 @@
+  $ $TESTDIR/run_test.sh disjunction.pl
+  Results : 
+  -? (reach ?X)
+  [?X = a];
+  [?X = b];
+  [?X = c];
+  -? ((edge ?X b) ; (start ?X))
+  [?X = a];
+  -? (edge ?X ?Y), (?Y = c ; ?X = x)
+  [?X = b, ?Y = c];
+  [?X = x, ?Y = y];
+  -? (color ?X) = (car ?X)
+  [?X = c1];
+  -? (painted ?X)
+  [?X = c1];
+  
+  $ cat > neg.pl <<EOF
+  > p(a).
+  > ?- \+ p(b) ; p(X).
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet neg.pl 2>/dev/null
+  Error : 
+   \+ p(b) needs a condition without \+
@@ -0,0 +1,23 @@
+  $ $TESTDIR/run_test.sh early_stop.pl
+  Results : 
+  -? (nat (s (s z)))
+  [];
+  -? (nat ?X)
+  [?X = z];
+  [?X = (s z)];
+  [?X = (s (s z))];
+  [?X = (s (s (s z)))];
+  
+  $ $TESTDIR/run_test.sh early_stop.pl --all-answers
+  Results : 
+  -? (nat (s (s z)))
+  [];
+  -? (nat ?X)
+  [?X = z];
+  [?X = (s z)];
+  [?X = (s (s z))];
+  [?X = (s (s (s z)))];
+  [?X = (s (s (s (s z))))];
+  [?X = (s (s (s (s (s z)))))];
+  [?X = (s (s (s (s (s (s z))))))];
+  
@@ -0,0 +1,16 @@
+  $ cat > comm.pl <<EOF
+  > % commutativity
+  > plus(X,Y) <- plus(Y,X).   /* one way */
+  > 
+  > 
+  > plus(p,r).
+  > ?-  plus(p,r) = plus(r, /* swapped */ p).
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet fmt comm.pl 2>/dev/null
+  $ cat comm.pl
+  % commutativity
+  plus(X, Y) <- plus(Y, X). /* one way */
+  
+  plus(p, r).
+  /* swapped */
+  ?- plus(p, r) = plus(r, p).
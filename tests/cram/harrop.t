@@ -0,0 +1,30 @@
+  $ $TESTDIR/run_test.sh harrop.pl
+  Results : 
+  -? (g a) = c
+  [];
+  -? (ancestor ?X ?Y)
+  [?X = ann, ?Y = bob];
+  -? (wiser ann bob)
+  [];
+  -? (has_parent ?X)
+  [?X = bob];
+  -? (parent #u#0)
+  [];
+  |- forall u, (forall x, child x u => parent u) => child bob u => parent u  by forallR
+    |- (forall x, child x '#u#0' => parent '#u#0') => child bob '#u#0' => parent '#u#0'  by =>R
+      (forall x, child x '#u#0' => parent '#u#0') |- child bob '#u#0' => parent '#u#0'  by =>R
+        (forall x, child x '#u#0' => parent '#u#0'), child bob '#u#0' |- parent '#u#0'  by -? (parent #u#0): proved
+  proved.
+  
+  $ cat > bad.pl <<EOF
+  > Axiom bad: forall x, p x => q x \/ r x.
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet bad.pl 2>/dev/null
+  Error : 
+   q x \/ r x can't be concluded by an axiom, only atoms and /\ of them can
+  $ cat > bad.pl <<EOF
+  > |- exists x, forall y, le x y.
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet bad.pl 2>/dev/null
+  Error : 
+   forall y, le x y can't be proven under an exists, which can only have atoms, /\ and \/ below it
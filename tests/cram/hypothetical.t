@@ -0,0 +1,52 @@
+  $ $TESTDIR/run_test.sh hypothetical.pl
+  Results : 
+  -? (f #a#0) = (f #b#1)
+  [];
+  |- forall a b, a = b => f a = f b  by forallR
+    |- '#a#0' = '#b#1' => f '#a#0' = f '#b#1'  by =>R
+      '#a#0' = '#b#1' |- f '#a#0' = f '#b#1'  by -? (f #a#0) = (f #b#1): proved
+  proved.
+  -? (h #a#2) = d
+  [];
+  |- forall a, a = c => h c = d => h a = d  by forallR
+    |- '#a#2' = c => h c = d => h '#a#2' = d  by =>R
+      '#a#2' = c |- h c = d => h '#a#2' = d  by =>R
+        '#a#2' = c, h c = d |- h '#a#2' = d  by -? (h #a#2) = d: proved
+  proved.
+  -? (q #a#3)
+  [];
+  |- forall a b, (forall x, p x => q x) => p a => q a  by forallR
+    |- (forall x, p x => q x) => p '#a#3' => q '#a#3'  by =>R
+      (forall x, p x => q x) |- p '#a#3' => q '#a#3'  by =>R
+        (forall x, p x => q x), p '#a#3' |- q '#a#3'  by -? (q #a#3): proved
+  proved.
+  -? (plus two one) = three, (g a) = (g b)
+  [];
+  |- a = b => plus two one = three /\ g a = g b  by =>R
+    a = b |- plus two one = three /\ g a = g b  by -? (plus two one) = three, (g a) = (g b): proved
+  proved.
+  -? a = b
+  unknown.
+  -? (q e)
+  unknown.
+  -? (plus two one) = three
+  [];
+  -? (g a) = (g b)
+  unknown.
+  
+  $ cat > run.pl <<EOF
+  > f(a) = c.
+  > :- run.
+  > |- a = b => f b = c.
+  > ?- f(b) = c.
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet run.pl 2>/dev/null
+  Results : 
+  -? (f b) = c
+  [];
+  |- a = b => f b = c  by =>R
+    a = b |- f b = c  by -? (f b) = c: proved
+  proved.
+  -? (f b) = c
+  unknown.
+  
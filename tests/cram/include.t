@@ -0,0 +1,69 @@
+Includes are found relative to the file that includes them. base.pl is reached twice, but only loaded once.
+
+  $ mkdir -p lib/nat
+  $ cat > main.pl <<EOF
+  > :- include(lib/arith.pl).
+  > :- include(lib/order.pl).
+  > ?- double(s(z)) = plus(s(z), s(z)).
+  > ?- le(z, s(z)).
+  > EOF
+  $ cat > lib/arith.pl <<EOF
+  > :- include(nat/base.pl).
+  > double(X) = plus(X, X) :- nat(X).
+  > EOF
+  $ cat > lib/order.pl <<EOF
+  > :- include(nat/base.pl).
+  > le(z, X) :- nat(X).
+  > EOF
+  $ cat > lib/nat/base.pl <<EOF
+  > nat(z).
+  > nat(s(X)) :- nat(X), nat_limit(X).
+  > nat_limit(z).
+  > ?- nat(z).
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet main.pl 2>/dev/null
+  Results : 
+  -? (nat z)
+  [];
+  -? (double (s z)) = (plus (s z) (s z))
+  [];
+  -? (le z (s z))
+  [];
+  
+
+Then the -I directories and EGGLOG_PATH.
+
+  $ mkdir -p libdir
+  $ cat > libdir/extra.pl <<EOF
+  > extra(a).
+  > EOF
+  $ cat > path.pl <<EOF
+  > :- include(extra.pl).
+  > ?- extra(X).
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet path.pl 2>/dev/null
+  Error : 
+   file extra.pl not found
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet -- -I libdir path.pl 2>/dev/null
+  Results : 
+  -? (extra ?X)
+  [?X = a];
+  
+  $ EGGLOG_PATH=libdir cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet path.pl 2>/dev/null
+  Results : 
+  -? (extra ?X)
+  [?X = a];
+  
+
+A file that includes itself, through another, is an error.
+
+  $ mkdir -p loop
+  $ cat > loop/a.pl <<EOF
+  > :- include(b.pl).
+  > EOF
+  $ cat > loop/b.pl <<EOF
+  > :- include(a.pl).
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet loop/a.pl 2>/dev/null
+  Error : 
+   include cycle */loop/a.pl -> */loop/b.pl -> */loop/a.pl (glob)
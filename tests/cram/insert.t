@@ -0,0 +1,25 @@
+  $ $TESTDIR/run_test.sh insert.pl
+  Results : 
+  -? (f (f (f y))) = y
+  unknown.
+  -? (f (f (f x))) = x
+  [];
+  -? (f (f (f (f (f a))))) = a, (f a) = a
+  [];
+  -? (f b) = b
+  unknown.
+  
+The terms a ?+ adds aren't there for a ?- before it, even when the file has no :- run.
+
+  $ cat > before.pl <<EOF
+  > X <- f(f(f(X))).
+  > ?- f(f(f(x))) = x.
+  > ?+ f(f(f(x))) = x.
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet before.pl 2>/dev/null
+  Results : 
+  -? (f (f (f x))) = x
+  unknown.
+  -? (f (f (f x))) = x
+  [];
+  
@@ -0,0 +1,20 @@
+  $ $TESTDIR/run_test.sh limits.pl
+  Results : 
+  -? (n (s (s z)))
+  [];
+  -? (n (s (s (s (s (s z))))))
+  unknown.
+  
+  $ $TESTDIR/run_test.sh limits.pl --iter-limit 10
+  Results : 
+  -? (n (s (s z)))
+  [];
+  -? (n (s (s (s (s (s z))))))
+  [];
+  
+A time limit has to be a number of seconds a run can wait for.
+
+  $ $TESTDIR/run_test.sh limits.pl --time-limit=-1
+  [2]
+  $ $TESTDIR/run_test.sh limits.pl --time-limit 1e20
+  [2]
@@ -0,0 +1,75 @@
+  $ $TESTDIR/run_test.sh negation.pl
+  Results : 
+  -? (unreachable ?X)
+  [?X = a];
+  [?X = d];
+  -? (sink ?X)
+  [?X = c];
+  [?X = d];
+  -? (item ?X) = (light ?X)
+  [?X = feather];
+  
+A query with a \+, even inside a ;, doesn't stop the run early, since r(a) is still to come.
+
+  $ cat > early.pl <<EOF
+  > p(a).
+  > s(a).
+  > r(X) :- s(X).
+  > ?- p(a), \+ r(a).
+  > ?- (p(a), \+ r(a) ; r(b)).
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet early.pl 2>/dev/null
+  Results : 
+  -? (p a), \+ (r a)
+  unknown.
+  -? ((p a), \+ (r a) ; (r b))
+  unknown.
+  
+  $ cat > cycle.pl <<EOF
+  > p(a).
+  > q(X) :- p(X), \+ r(X).
+  > r(X) :- q(X).
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet cycle.pl 2>/dev/null
+  Error : 
+   \+ r is in a recursive cycle with q, in (q ?X):-(p ?X), \+ (r ?X).
+
+Merging eclasses can make a negation false, as b = a gives a the r that b has,
+so rules that merge count as adding every negated symbol.
+
+  $ cat > merge.pl <<EOF
+  > q(X) :- p(X), \+ r(X).
+  > a = b :- q(a).
+  > p(a).
+  > r(b).
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet merge.pl 2>/dev/null
+  Error : 
+   \+ r is in a recursive cycle with q, since rules that merge eclasses (rewrites, or = in a head) count as adding every negated symbol, in (q ?X):-(p ?X), \+ (r ?X).
+
+A rewrite guarded by \+ runs last, with the rules that look for what it adds.
+
+  $ cat > last.pl <<EOF
+  > item(anvil).
+  > item(feather).
+  > heavy(X) :- item(X), big(X).
+  > big(anvil).
+  > light(X) <- item(X), \+ heavy(X).
+  > carry(X) :- light(X).
+  > ?- carry(X).
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet last.pl 2>/dev/null
+  Results : 
+  -? (carry ?X)
+  [?X = feather];
+  
+
+None of those can add what it negates.
+
+  $ cat > guard.pl <<EOF
+  > light(X) <- item(X), \+ heavy(X).
+  > heavy(X) :- light(X), big(X).
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet guard.pl 2>/dev/null
+  Error : 
+   \+ heavy is added by a rule that runs last with the rewrites guarded by \+, in (item ?X), \+ (heavy ?X) -[]> (light ?X)
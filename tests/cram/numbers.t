@@ -0,0 +1,9 @@
+  $ $TESTDIR/run_test.sh numbers.pl
+  Results : 
+  -? (fib 20) = ?X
+  [?X = 6765];
+  -? (+ half third) = ?X
+  [?X = 5/6];
+  -? (name ?X) = "Ann"
+  [?X = ann];
+  
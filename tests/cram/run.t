@@ -0,0 +1,19 @@
+  $ $TESTDIR/run_test.sh run.pl
+  Results : 
+  -? (path a b)
+  unknown.
+  -? (path a b)
+  [];
+  -? (path a c)
+  unknown.
+  -? (path a c)
+  [];
+  -? (path a d)
+  unknown.
+  -? (path a d)
+  [];
+  -? (path a e)
+  unknown.
+  -? (path a e)
+  [];
+  
@@ -1 +1,3 @@
-cargo run  --manifest-path "$TESTDIR/../../Cargo.toml" --quiet "$TESTDIR/../../examples/$1" 2>/dev/null
\ No newline at end of file
+file=$1
+shift
+cargo run  --manifest-path "$TESTDIR/../../Cargo.toml" --quiet "$TESTDIR/../../examples/$file" "$@" 2>/dev/null
@@ -0,0 +1,13 @@
+  $ $TESTDIR/run_test.sh schedule.pl
+  Results : 
+  -? (path a c)
+  unknown.
+  -? (path a d)
+  [];
+  -? (plus a (plus b c)) = (plus c (plus b a))
+  [];
+  -? (path d e)
+  [];
+  -? (path a e)
+  unknown.
+  
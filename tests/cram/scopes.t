@@ -0,0 +1,15 @@
+  $ $TESTDIR/run_test.sh scopes.pl
+  Results : 
+  -? four = (s (s (s (s z))))
+  [];
+  -? four = (plus three three)
+  [];
+  -? four = (plus three three)
+  unknown.
+  -? (double two) = four
+  [];
+  -? (double two) = four
+  unknown.
+  -? two = (s (s z))
+  unknown.
+  
@@ -0,0 +1,29 @@
+  $ $TESTDIR/run_test.sh sequent.pl
+  Results : 
+  -? (f #u#0) = (f w)
+  [];
+  -? (le a w)
+  [];
+  |- forall u v, u = v /\ v = w => f u = f w /\ (le a v => le a w)  by forallR
+    |- '#u#0' = '#v#1' /\ '#v#1' = w => f '#u#0' = f w /\ (le a '#v#1' => le a w)  by =>R
+      '#u#0' = '#v#1' /\ '#v#1' = w |- f '#u#0' = f w /\ (le a '#v#1' => le a w)  by /\L
+        '#u#0' = '#v#1', '#v#1' = w |- f '#u#0' = f w /\ (le a '#v#1' => le a w)  by /\R
+          '#u#0' = '#v#1', '#v#1' = w |- f '#u#0' = f w  by -? (f #u#0) = (f w): proved
+          '#u#0' = '#v#1', '#v#1' = w |- le a '#v#1' => le a w  by =>R
+            '#u#0' = '#v#1', '#v#1' = w, le a '#v#1' |- le a w  by -? (le a w): proved
+  proved.
+  -? (le a #u#2)
+  [];
+  -? (le #u#2 c)
+  unknown.
+  -? (le a #u#2)
+  [];
+  |- forall u, le b u => le a u /\ (le u c \/ le a u)  by forallR
+    |- le b '#u#2' => le a '#u#2' /\ (le '#u#2' c \/ le a '#u#2')  by =>R
+      le b '#u#2' |- le a '#u#2' /\ (le '#u#2' c \/ le a '#u#2')  by /\R
+        le b '#u#2' |- le a '#u#2'  by -? (le a #u#2): proved
+        le b '#u#2' |- le '#u#2' c \/ le a '#u#2'  by \/R
+          le b '#u#2' |- le '#u#2' c  by -? (le #u#2 c): unknown
+          le b '#u#2' |- le a '#u#2'  by -? (le a #u#2): proved
+  proved.
+  
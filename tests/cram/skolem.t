@@ -0,0 +1,9 @@
+  $ $TESTDIR/run_test.sh skolem.pl
+  Results : 
+  -? (lt z ?Y) = true, (lt ?Y ?W) = true
+  [?Y = ('#sk_y#0' z), ?W = ('#sk_y#0' ('#sk_y#0' z))];
+  -? (mul b ?Z) = one
+  [?Z = ('#sk_z#1' a b)];
+  -? (point ?O)
+  [?O = '#sk_o#2'];
+  
@@ -0,0 +1,5 @@
+  $ $TESTDIR/run_test.sh group.smt2
+  unsat
+  $ $TESTDIR/run_test.sh order.smt2
+  unknown
+  unsat
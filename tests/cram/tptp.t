@@ -0,0 +1,6 @@
+  $ $TESTDIR/run_test.sh tptp/cancellation.p
+  % SZS status Unsatisfiable for cancellation
+  $ $TESTDIR/run_test.sh tptp/inverse_identity.p
+  % SZS status GaveUp for inverse_identity
+  $ $TESTDIR/run_test.sh tptp/transitivity.p
+  % SZS status Theorem for transitivity
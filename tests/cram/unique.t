@@ -0,0 +1,39 @@
+  $ $TESTDIR/run_test.sh unique.pl
+  Results : 
+  -? one = id2
+  [];
+  -? b = c
+  [];
+  -? (identity ?e)
+  [?e = '#sk_e#0'];
+  |- exists! e, identity e  by -? (identity ?e), one witness: proved
+  proved.
+  -? (inverse a ?y)
+  [?y = b];
+  |- exists! y, inverse a y  by -? (inverse a ?y), one witness: proved
+  proved.
+  -? (elem ?x)
+  [?x = a];
+  [?x = b];
+  |- exists! x, elem x  by -? (elem ?x), one witness: unknown
+  unknown.
+  
+A witness that some answer leaves unbound, or that is never bound at all, isn't unique.
+
+  $ cat > unbound.pl <<EOF
+  > p(a).
+  > q.
+  > |- exists! x, p x \/ q.
+  > |- exists! x, p a.
+  > EOF
+  $ cargo run --manifest-path "$TESTDIR/../../Cargo.toml" --quiet unbound.pl 2>/dev/null
+  Results : 
+  -? ((p ?x) ; q)
+  [];
+  |- exists! x, p x \/ q  by -? ((p ?x) ; q), one witness: unknown
+  unknown.
+  -? (p a)
+  [];
+  |- exists! x, p a  by -? (p a), one witness: unknown
+  unknown.
+  